@@ -0,0 +1,456 @@
+use plotters::coord::Shift;
+use plotters::style::text_anchor::{HPos, Pos, VPos};
+use plotters::prelude::*;
+
+use crate::histogram::LatencyHistogram;
+use crate::resources::ResourceSample;
+
+/// The percentiles logged and charted for latency distributions by default.
+pub const DEFAULT_PERCENTILES: [f64; 6] = [50.0, 75.0, 90.0, 95.0, 99.0, 99.9];
+
+/// Controls how the results are logged and charted.
+#[derive(Debug, Clone)]
+pub struct ChartOptions {
+    /// The width and height of the charts in pixels.
+    pub size: (u32, u32),
+
+    /// The percentiles logged and charted for latency distributions.
+    pub percentiles: Vec<f64>,
+}
+
+impl Default for ChartOptions {
+    fn default() -> Self {
+        Self {
+            size: (1920, 1080),
+            percentiles: DEFAULT_PERCENTILES.to_vec(),
+        }
+    }
+}
+
+/// Draws a chart to a PNG file.
+pub(crate) fn save_png<F>(output: &str, options: &ChartOptions, draw: F) -> anyhow::Result<()>
+where
+    F: FnOnce(&DrawingArea<BitMapBackend, Shift>) -> anyhow::Result<()>,
+{
+    let root = BitMapBackend::new(output, options.size).into_drawing_area();
+
+    root.fill(&WHITE)?;
+    draw(&root)?;
+
+    // To avoid the IO failure being ignored silently, we manually call the present function
+    root.present()?;
+
+    Ok(())
+}
+
+/// Draws a chart to an SVG document.
+pub(crate) fn render_svg<F>(options: &ChartOptions, draw: F) -> anyhow::Result<String>
+where
+    F: FnOnce(&DrawingArea<SVGBackend, Shift>) -> anyhow::Result<()>,
+{
+    let mut svg = String::new();
+
+    {
+        let root = SVGBackend::with_string(&mut svg, options.size).into_drawing_area();
+
+        root.fill(&WHITE)?;
+        draw(&root)?;
+        root.present()?;
+    }
+
+    Ok(svg)
+}
+
+/// Draws the average latency of each sentence length as a bar chart.
+pub(crate) fn sentence_lengths<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    sentence_length_latencies: &[LatencyHistogram],
+) -> anyhow::Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let mut data: Vec<u32> = vec![0; sentence_length_latencies.len().saturating_sub(1)];
+    for (length, latencies) in sentence_length_latencies.iter().enumerate() {
+        if length == 0 {
+            continue;
+        }
+
+        data[length-1] = (latencies.mean() / 1000.0) as u32;
+    }
+    let max_latency = data.iter().copied().max().unwrap_or(0u32);
+    let max_length = data.len() as u32;
+
+    let mut chart = ChartBuilder::on(root)
+        .x_label_area_size(75)
+        .y_label_area_size(75)
+        .margin(5)
+        .caption("Searching Latency Graph", ("sans-serif", 50.0))
+        .build_cartesian_2d((1u32..max_length).into_segmented(), 0u32..max_latency)?;
+
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .bold_line_style(WHITE.mix(0.5))
+        .y_desc("Avg Latency (ms)")
+        .x_desc("Sentence Length")
+        .label_style(("sans-serif", 32))
+        .axis_desc_style(("sans-serif", 48))
+        .draw()?;
+
+    chart.draw_series(
+        Histogram::vertical(&chart)
+            .style(RED.mix(0.5).filled())
+            .data(data.iter().enumerate().map(|(y, x)| ((y+1) as u32, *x))),
+    )?;
+
+    Ok(())
+}
+
+/// Draws a line for each histogram showing the latency at each percentile.
+pub(crate) fn percentile_lines<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    options: &ChartOptions,
+    caption: &str,
+    series: &[(&str, &LatencyHistogram, RGBColor)],
+) -> anyhow::Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let percentiles = &options.percentiles;
+    let as_millis = |v: u64| v as f64 / 1000.0;
+    let highest = percentiles.iter().copied().fold(0.0, f64::max);
+    let max_latency = series
+        .iter()
+        .map(|(_, hist, _)| as_millis(hist.value_at_percentile(highest)))
+        .fold(0.001, f64::max);
+
+    let mut chart = ChartBuilder::on(root)
+        .x_label_area_size(75)
+        .y_label_area_size(75)
+        .margin(5)
+        .caption(caption, ("sans-serif", 50.0))
+        .build_cartesian_2d(
+            (0..percentiles.len().saturating_sub(1)).into_segmented(),
+            0f64..max_latency * 1.1,
+        )?;
+
+    chart
+        .configure_mesh()
+        .disable_x_mesh()
+        .bold_line_style(WHITE.mix(0.5))
+        .x_label_formatter(&|v| match v {
+            SegmentValue::CenterOf(i) => percentiles
+                .get(*i)
+                .map(|p| format!("p{}", p))
+                .unwrap_or_default(),
+            _ => String::new(),
+        })
+        .y_desc("Latency (ms)")
+        .x_desc("Percentile")
+        .label_style(("sans-serif", 32))
+        .axis_desc_style(("sans-serif", 48))
+        .draw()?;
+
+    for (name, hist, colour) in series.iter() {
+        let colour = *colour;
+        chart
+            .draw_series(LineSeries::new(
+                percentiles
+                    .iter()
+                    .enumerate()
+                    .map(|(i, p)| (SegmentValue::CenterOf(i), as_millis(hist.value_at_percentile(*p)))),
+                colour.stroke_width(3),
+            ))?
+            .label(*name)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 40, y)], colour.stroke_width(3)));
+    }
+
+    chart
+        .configure_series_labels()
+        .label_font(("sans-serif", 32))
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .position(SeriesLabelPosition::UpperLeft)
+        .draw()?;
+
+    Ok(())
+}
+
+/// Draws the server resource usage next to the benchmark throughput and
+/// latency on a shared time axis.
+pub(crate) fn resource_timeline<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    samples: &[ResourceSample],
+) -> anyhow::Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let root = root.titled("Server Resources Over Time", ("sans-serif", 50.0))?;
+    let panels = root.split_evenly((3, 2));
+
+    let series = |f: &dyn Fn(&ResourceSample) -> Option<f64>| -> Vec<(f64, f64)> {
+        samples
+            .iter()
+            .filter_map(|s| f(s).map(|v| (s.elapsed, v)))
+            .collect()
+    };
+
+    draw_timeline_panel(&panels[0], "Throughput", "Requests/sec", &[
+        ("Requests/sec", series(&|s| Some(s.requests_second)), RED),
+    ])?;
+    draw_timeline_panel(&panels[1], "Latency", "Mean Latency (ms)", &[
+        ("Mean Latency", series(&|s| Some(s.mean_latency_ms)), RED),
+    ])?;
+    draw_timeline_panel(&panels[2], "CPU", "CPU (%)", &[
+        ("CPU", series(&|s| Some(s.cpu_percent)), BLUE),
+    ])?;
+    draw_timeline_panel(&panels[3], "Memory", "RSS (MB)", &[
+        ("RSS", series(&|s| Some(s.rss_bytes as f64 / 1_000_000.0)), BLUE),
+    ])?;
+    draw_timeline_panel(&panels[4], "Threads & Files", "Count", &[
+        ("Threads", series(&|s| Some(s.threads as f64)), GREEN),
+        ("Open Files", series(&|s| Some(s.open_fds as f64)), MAGENTA),
+    ])?;
+    draw_timeline_panel(&panels[5], "Disk IO", "KB/sec", &[
+        ("Read", series(&|s| s.disk_read_bytes_second.map(|v| v / 1000.0)), GREEN),
+        ("Write", series(&|s| s.disk_write_bytes_second.map(|v| v / 1000.0)), MAGENTA),
+    ])?;
+
+    Ok(())
+}
+
+/// A named line of `(elapsed seconds, value)` points.
+type TimelineSeries<'a> = (&'a str, Vec<(f64, f64)>, RGBColor);
+
+fn draw_timeline_panel<DB: DrawingBackend>(
+    area: &DrawingArea<DB, Shift>,
+    caption: &str,
+    y_desc: &str,
+    series: &[TimelineSeries],
+) -> anyhow::Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let max_x = series
+        .iter()
+        .flat_map(|(_, points, _)| points.iter().map(|(x, _)| *x))
+        .fold(1.0, f64::max);
+    let max_y = series
+        .iter()
+        .flat_map(|(_, points, _)| points.iter().map(|(_, y)| *y))
+        .fold(1.0, f64::max);
+
+    let mut chart = ChartBuilder::on(area)
+        .x_label_area_size(50)
+        .y_label_area_size(75)
+        .margin(10)
+        .caption(caption, ("sans-serif", 32.0))
+        .build_cartesian_2d(0f64..max_x, 0f64..max_y * 1.1)?;
+
+    chart
+        .configure_mesh()
+        .bold_line_style(WHITE.mix(0.5))
+        .y_desc(y_desc)
+        .x_desc("Elapsed (s)")
+        .label_style(("sans-serif", 20))
+        .axis_desc_style(("sans-serif", 24))
+        .draw()?;
+
+    for (name, points, colour) in series.iter() {
+        let colour = *colour;
+        chart
+            .draw_series(LineSeries::new(points.iter().copied(), colour.stroke_width(2)))?
+            .label(*name)
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 30, y)], colour.stroke_width(2)));
+    }
+
+    chart
+        .configure_series_labels()
+        .label_font(("sans-serif", 20))
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .position(SeriesLabelPosition::UpperRight)
+        .draw()?;
+
+    Ok(())
+}
+
+/// A grid of values drawn as a heatmap, the rows and columns are the
+/// values swept along each axis.
+pub(crate) struct Heatmap<'a> {
+    pub(crate) caption: String,
+    pub(crate) row_desc: &'a str,
+    pub(crate) rows: &'a [usize],
+    pub(crate) column_desc: &'a str,
+    pub(crate) columns: &'a [usize],
+
+    /// The value of each cell row by row, cells without a value are left blank.
+    pub(crate) values: Vec<Option<f64>>,
+
+    /// The format of the value labelled on each cell.
+    pub(crate) label: fn(f64) -> String,
+
+    /// If higher values are better, the best cells are drawn green and the
+    /// worst red.
+    pub(crate) higher_is_better: bool,
+}
+
+/// Draws the heatmaps in a grid with two heatmaps on each row.
+pub(crate) fn heatmaps<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    caption: &str,
+    maps: &[Heatmap],
+) -> anyhow::Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let root = root.titled(caption, ("sans-serif", 50.0))?;
+    let rows = maps.len().div_ceil(2);
+    let panels = root.split_evenly((rows.max(1), 2));
+
+    for (map, area) in maps.iter().zip(panels.iter()) {
+        draw_heatmap(area, map)?;
+    }
+
+    Ok(())
+}
+
+fn draw_heatmap<DB: DrawingBackend>(area: &DrawingArea<DB, Shift>, map: &Heatmap) -> anyhow::Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let (n_rows, n_columns) = (map.rows.len(), map.columns.len());
+    let min = map.values.iter().flatten().copied().fold(f64::INFINITY, f64::min);
+    let max = map.values.iter().flatten().copied().fold(f64::NEG_INFINITY, f64::max);
+
+    // The cells are centred on whole numbers so the labels line up with them.
+    let mut chart = ChartBuilder::on(area)
+        .x_label_area_size(50)
+        .y_label_area_size(75)
+        .margin(10)
+        .caption(&map.caption, ("sans-serif", 32.0))
+        .build_cartesian_2d(-0.5f64..n_columns as f64 - 0.5, -0.5f64..n_rows as f64 - 0.5)?;
+
+    chart
+        .configure_mesh()
+        .disable_mesh()
+        .x_labels(n_columns)
+        .y_labels(n_rows)
+        .x_label_formatter(&|v| axis_label(map.columns, *v))
+        .y_label_formatter(&|v| axis_label(map.rows, *v))
+        .x_desc(map.column_desc)
+        .y_desc(map.row_desc)
+        .label_style(("sans-serif", 20))
+        .axis_desc_style(("sans-serif", 24))
+        .draw()?;
+
+    let cells = map.values.iter().enumerate().filter_map(|(i, value)| {
+        value.map(|v| ((i % n_columns) as f64, (i / n_columns) as f64, v))
+    });
+
+    for (x, y, value) in cells {
+        // 0 is the best value and 1 the worst.
+        let mut position = if max > min { (value - min) / (max - min) } else { 0.0 };
+        if map.higher_is_better {
+            position = 1.0 - position;
+        }
+        let colour = HSLColor((1.0 - position) / 3.0, 0.7, 0.6);
+
+        chart.draw_series(std::iter::once(Rectangle::new(
+            [(x - 0.5, y - 0.5), (x + 0.5, y + 0.5)],
+            colour.filled(),
+        )))?;
+        chart.draw_series(std::iter::once(Text::new(
+            (map.label)(value),
+            (x, y),
+            ("sans-serif", 24)
+                .into_font()
+                .color(&BLACK)
+                .pos(Pos::new(HPos::Center, VPos::Center)),
+        )))?;
+    }
+
+    Ok(())
+}
+
+fn axis_label(values: &[usize], position: f64) -> String {
+    values
+        .get(position.round().max(0.0) as usize)
+        .map(|v| v.to_string())
+        .unwrap_or_default()
+}
+
+/// A run plotted on the latency and throughput trade-off chart.
+pub(crate) struct TradeoffPoint {
+    pub(crate) label: String,
+    pub(crate) latency_ms: f64,
+    pub(crate) requests_second: f64,
+    pub(crate) pareto_optimal: bool,
+}
+
+/// Draws each run's p99 latency against its throughput, the runs which
+/// weren't beaten on both are highlighted.
+pub(crate) fn tradeoff<DB: DrawingBackend>(
+    root: &DrawingArea<DB, Shift>,
+    caption: &str,
+    points: &[TradeoffPoint],
+) -> anyhow::Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let max_latency = points.iter().map(|p| p.latency_ms).fold(0.001, f64::max);
+    let max_throughput = points.iter().map(|p| p.requests_second).fold(0.001, f64::max);
+
+    let mut chart = ChartBuilder::on(root)
+        .x_label_area_size(75)
+        .y_label_area_size(110)
+        .margin(5)
+        .caption(caption, ("sans-serif", 50.0))
+        .build_cartesian_2d(0f64..max_latency * 1.1, 0f64..max_throughput * 1.1)?;
+
+    chart
+        .configure_mesh()
+        .bold_line_style(WHITE.mix(0.5))
+        .x_desc("p99 Latency (ms)")
+        .y_desc("Requests/sec")
+        .label_style(("sans-serif", 32))
+        .axis_desc_style(("sans-serif", 48))
+        .draw()?;
+
+    let mut frontier: Vec<(f64, f64)> = points
+        .iter()
+        .filter(|p| p.pareto_optimal)
+        .map(|p| (p.latency_ms, p.requests_second))
+        .collect();
+    frontier.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    chart
+        .draw_series(LineSeries::new(frontier, GREEN.stroke_width(2)))?
+        .label("Pareto Optimal")
+        .legend(|(x, y)| PathElement::new(vec![(x, y), (x + 40, y)], GREEN.stroke_width(3)));
+
+    for point in points {
+        let colour = if point.pareto_optimal { GREEN } else { RED };
+        let position = (point.latency_ms, point.requests_second);
+
+        chart.draw_series(std::iter::once(Circle::new(position, 10, colour.filled())))?;
+        chart.draw_series(std::iter::once(Text::new(
+            point.label.clone(),
+            position,
+            ("sans-serif", 28)
+                .into_font()
+                .color(&BLACK)
+                .pos(Pos::new(HPos::Left, VPos::Bottom)),
+        )))?;
+    }
+
+    chart
+        .configure_series_labels()
+        .label_font(("sans-serif", 32))
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .position(SeriesLabelPosition::UpperRight)
+        .draw()?;
+
+    Ok(())
+}
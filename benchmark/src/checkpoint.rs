@@ -0,0 +1,148 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+
+/// The progress of an upload saved to a file so an interrupted upload can
+/// be resumed rather than started over.
+///
+/// The checkpoint is only valid for the same data file, index and batch size
+/// as the batches are identified by their position in the data file.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Checkpoint {
+    data_file: String,
+    index: String,
+    batch_size: usize,
+
+    /// The batches which have been uploaded and committed.
+    completed: BTreeSet<usize>,
+
+    #[serde(skip)]
+    path: String,
+}
+
+impl Checkpoint {
+    /// Loads the checkpoint of an interrupted upload or starts a new one if
+    /// the file doesn't exist.
+    pub(crate) fn load(
+        path: &str,
+        data_file: &str,
+        index: &str,
+        batch_size: usize,
+    ) -> anyhow::Result<Self> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return Ok(Self {
+                    data_file: data_file.to_string(),
+                    index: index.to_string(),
+                    batch_size,
+                    completed: BTreeSet::new(),
+                    path: path.to_string(),
+                })
+            },
+            Err(e) => {
+                return Err(anyhow!(
+                    "failed to read the upload checkpoint {:?}: {}",
+                    path,
+                    e
+                ))
+            },
+        };
+
+        let mut checkpoint: Self = serde_json::from_slice(&data)
+            .map_err(|e| anyhow!("invalid upload checkpoint {:?}: {}", path, e))?;
+
+        if checkpoint.data_file != data_file
+            || checkpoint.index != index
+            || checkpoint.batch_size != batch_size
+        {
+            return Err(anyhow!(
+                "the upload checkpoint {:?} is for the data file {:?} uploaded to the index {:?} in batches of {}, delete it to start the upload over",
+                path,
+                checkpoint.data_file,
+                checkpoint.index,
+                checkpoint.batch_size,
+            ));
+        }
+
+        checkpoint.path = path.to_string();
+        Ok(checkpoint)
+    }
+
+    /// If the checkpoint has been saved, either by this run or the run
+    /// being resumed.
+    pub(crate) fn is_saved(&self) -> bool {
+        Path::new(&self.path).exists()
+    }
+
+    /// If some of the batches were uploaded by a previous run.
+    pub(crate) fn is_resumed(&self) -> bool {
+        !self.completed.is_empty()
+    }
+
+    pub(crate) fn is_completed(&self, batch: usize) -> bool {
+        self.completed.contains(&batch)
+    }
+
+    pub(crate) fn complete(&mut self, batch: usize) {
+        self.completed.insert(batch);
+    }
+
+    /// Saves the checkpoint, this should only be done once the completed
+    /// batches have been committed.
+    ///
+    /// The file is replaced in one step so an interruption can't leave a
+    /// partially written checkpoint behind.
+    pub(crate) fn save(&self) -> anyhow::Result<()> {
+        let temp = format!("{}.tmp", self.path);
+        fs::write(&temp, serde_json::to_vec(self)?)?;
+        fs::rename(&temp, &self.path)?;
+
+        Ok(())
+    }
+
+    /// Removes the checkpoint once the upload has finished.
+    pub(crate) fn remove(&self) -> anyhow::Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resumes_the_same_upload_only() {
+        let path =
+            std::env::temp_dir().join(format!("lnxcli-checkpoint-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut checkpoint = Checkpoint::load(path, "movies.json", "bench", 100).unwrap();
+        assert!(!checkpoint.is_resumed());
+
+        checkpoint.complete(0);
+        checkpoint.complete(2);
+        checkpoint.save().unwrap();
+
+        let resumed = Checkpoint::load(path, "movies.json", "bench", 100).unwrap();
+        assert!(resumed.is_resumed());
+        assert!(resumed.is_completed(0));
+        assert!(!resumed.is_completed(1));
+        assert!(resumed.is_completed(2));
+
+        assert!(Checkpoint::load(path, "movies.json", "bench", 50).is_err());
+        assert!(Checkpoint::load(path, "movies.json", "other", 100).is_err());
+
+        resumed.remove().unwrap();
+        assert!(!Checkpoint::load(path, "movies.json", "bench", 100)
+            .unwrap()
+            .is_resumed());
+    }
+}
@@ -0,0 +1,371 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+
+use anyhow::anyhow;
+use serde::de::{self, SeqAccess, Visitor};
+use serde::Deserializer;
+use serde_json::{Map, Value};
+use tokio::sync::mpsc;
+
+/// The number of batches read ahead of the upload, this bounds the number of
+/// documents held in memory.
+const READ_AHEAD_BATCHES: usize = 2;
+
+/// The layout of the documents in the data file.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum Format {
+    /// A single JSON array of documents.
+    JsonArray,
+
+    /// One JSON document per line, also known as NDJSON.
+    JsonLines,
+
+    /// A CSV file with a header row naming the fields.
+    Csv,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+/// Works out the format and compression of the data file from its
+/// extension, e.g. `movies.jsonl.gz`.
+///
+/// A `.json` file is detected as a JSON array or JSON lines once opened.
+pub(crate) fn detect(path: &str) -> anyhow::Result<(Option<Format>, Compression)> {
+    let lower = path.to_lowercase();
+    let (name, compression) = if let Some(name) = lower.strip_suffix(".gz") {
+        (name, Compression::Gzip)
+    } else if let Some(name) = lower
+        .strip_suffix(".zst")
+        .or_else(|| lower.strip_suffix(".zstd"))
+    {
+        (name, Compression::Zstd)
+    } else {
+        (lower.as_str(), Compression::None)
+    };
+
+    let format = match name.rsplit('.').next() {
+        Some("json") => None,
+        Some("jsonl") | Some("ndjson") => Some(Format::JsonLines),
+        Some("csv") => Some(Format::Csv),
+        _ => {
+            return Err(anyhow!(
+                "unknown data file format {:?}, expected a .json, .jsonl, .ndjson or .csv file optionally compressed with .gz or .zst",
+                path
+            ))
+        },
+    };
+
+    Ok((format, compression))
+}
+
+/// The documents of the data file read in batches.
+///
+/// The file is read on a blocking thread which stays a bounded number of
+/// batches ahead of the consumer so any size of dataset can be uploaded.
+pub(crate) struct Documents {
+    batches: mpsc::Receiver<anyhow::Result<Vec<Value>>>,
+    peeked: Option<Vec<Value>>,
+    read: usize,
+}
+
+impl Documents {
+    pub(crate) fn open(path: &str, batch_size: usize) -> anyhow::Result<Self> {
+        if batch_size == 0 {
+            return Err(anyhow!("the batch size must be greater than 0"));
+        }
+
+        let (format, compression) = detect(path)?;
+        let file = File::open(path)
+            .map_err(|e| anyhow!("failed to open the data file {:?}: {}", path, e))?;
+        let reader: Box<dyn Read + Send> = match compression {
+            Compression::None => Box::new(file),
+            Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(file)),
+            Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(file)?),
+        };
+        let mut reader = BufReader::new(reader);
+
+        let (tx, rx) = mpsc::channel(READ_AHEAD_BATCHES);
+        let path = path.to_string();
+        tokio::task::spawn_blocking(move || {
+            let mut batcher = Batcher {
+                tx: &tx,
+                batch: Vec::with_capacity(batch_size),
+                batch_size,
+            };
+
+            let result = match format {
+                Some(format) => read(&mut reader, format, &mut batcher),
+                None => detect_json(&mut reader)
+                    .and_then(|format| read(&mut reader, format, &mut batcher)),
+            };
+
+            let result = result.and_then(|_| batcher.flush());
+            if let Err(e) = result {
+                // The receiver is only gone if the upload has already failed.
+                let _ = tx.blocking_send(Err(anyhow!(
+                    "failed to read the data file {:?}: {}",
+                    path,
+                    e
+                )));
+            }
+        });
+
+        Ok(Self {
+            batches: rx,
+            peeked: None,
+            read: 0,
+        })
+    }
+
+    /// The first batch of documents, this is still returned by the next
+    /// call to `next_batch`.
+    pub(crate) async fn sample(&mut self) -> anyhow::Result<&[Value]> {
+        if self.peeked.is_none() {
+            self.peeked = Some(self.next_batch().await?.unwrap_or_default());
+        }
+
+        Ok(self.peeked.as_deref().unwrap_or_default())
+    }
+
+    /// The next batch of documents or `None` once every document has
+    /// been read.
+    pub(crate) async fn next_batch(&mut self) -> anyhow::Result<Option<Vec<Value>>> {
+        if let Some(batch) = self.peeked.take() {
+            return Ok(Some(batch).filter(|batch| !batch.is_empty()));
+        }
+
+        match self.batches.recv().await {
+            Some(batch) => {
+                let batch = batch?;
+                self.read += batch.len();
+                Ok(Some(batch))
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// The number of documents read so far.
+    pub(crate) fn read(&self) -> usize {
+        self.read
+    }
+}
+
+/// Groups the documents into batches and sends them to the uploader.
+struct Batcher<'a> {
+    tx: &'a mpsc::Sender<anyhow::Result<Vec<Value>>>,
+    batch: Vec<Value>,
+    batch_size: usize,
+}
+
+impl<'a> Batcher<'a> {
+    fn push(&mut self, doc: Value) -> anyhow::Result<()> {
+        self.batch.push(doc);
+        if self.batch.len() >= self.batch_size {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> anyhow::Result<()> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+
+        let batch = std::mem::replace(&mut self.batch, Vec::with_capacity(self.batch_size));
+        self.tx
+            .blocking_send(Ok(batch))
+            .map_err(|_| anyhow!("the upload stopped before every document was read"))
+    }
+}
+
+/// Looks at the first character of a `.json` file to tell a JSON array from
+/// JSON lines, any leading whitespace is skipped.
+fn detect_json(reader: &mut impl BufRead) -> anyhow::Result<Format> {
+    loop {
+        let buf = reader.fill_buf()?;
+        let first = match buf.first() {
+            Some(first) => *first,
+            None => return Err(anyhow!("the data file is empty")),
+        };
+
+        if first.is_ascii_whitespace() {
+            let whitespace = buf.iter().take_while(|b| b.is_ascii_whitespace()).count();
+            reader.consume(whitespace);
+            continue;
+        }
+
+        return Ok(if first == b'[' {
+            Format::JsonArray
+        } else {
+            Format::JsonLines
+        });
+    }
+}
+
+fn read(reader: &mut impl BufRead, format: Format, batcher: &mut Batcher) -> anyhow::Result<()> {
+    match format {
+        Format::JsonArray => {
+            let mut error = None;
+            let mut deserializer = serde_json::Deserializer::from_reader(reader);
+            let result = deserializer.deserialize_seq(ArrayVisitor {
+                on_doc: |doc| {
+                    batcher.push(doc).map_err(|e| {
+                        let message = e.to_string();
+                        error = Some(e);
+                        message
+                    })
+                },
+            });
+
+            match (result, error) {
+                (_, Some(e)) => Err(e),
+                (Err(e), None) => Err(e.into()),
+                (Ok(()), None) => Ok(deserializer.end()?),
+            }
+        },
+        Format::JsonLines => {
+            for (i, line) in reader.lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let doc = serde_json::from_str(&line)
+                    .map_err(|e| anyhow!("invalid JSON on line {}: {}", i + 1, e))?;
+                batcher.push(doc)?;
+            }
+
+            Ok(())
+        },
+        Format::Csv => {
+            let mut csv = csv::Reader::from_reader(reader);
+            let headers = csv.headers()?.clone();
+            for record in csv.records() {
+                batcher.push(csv_document(&headers, &record?))?;
+            }
+
+            Ok(())
+        },
+    }
+}
+
+/// Converts a CSV record to a document.
+///
+/// Numbers are converted to JSON numbers except in the `id` column and
+/// empty values are left out.
+fn csv_document(headers: &csv::StringRecord, record: &csv::StringRecord) -> Value {
+    let mut doc = Map::new();
+    for (name, value) in headers.iter().zip(record.iter()) {
+        if value.is_empty() {
+            continue;
+        }
+
+        let value = if name == "id" {
+            Value::from(value)
+        } else if let Ok(v) = value.parse::<i64>() {
+            Value::from(v)
+        } else if let Some(v) = value.parse::<f64>().ok().filter(|v| v.is_finite()) {
+            Value::from(v)
+        } else {
+            Value::from(value)
+        };
+
+        doc.insert(name.to_string(), value);
+    }
+
+    Value::Object(doc)
+}
+
+/// Passes each element of a JSON array to the callback as it is parsed
+/// rather than collecting the whole array.
+struct ArrayVisitor<F> {
+    on_doc: F,
+}
+
+impl<'de, F> Visitor<'de> for ArrayVisitor<F>
+where
+    F: FnMut(Value) -> Result<(), String>,
+{
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an array of documents")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(mut self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(doc) = seq.next_element::<Value>()? {
+            (self.on_doc)(doc).map_err(de::Error::custom)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn read_all(path: &str, batch_size: usize) -> Vec<Vec<Value>> {
+        let mut docs = Documents::open(path, batch_size).unwrap();
+        let mut batches = vec![];
+        while let Some(batch) = docs.next_batch().await.unwrap() {
+            batches.push(batch);
+        }
+
+        batches
+    }
+
+    #[tokio::test]
+    async fn reads_every_format_in_batches() {
+        let dir = std::env::temp_dir().join(format!("lnxcli-dataset-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let expected = vec![
+            serde_json::json!({ "id": "1", "title": "Up", "year": 2009 }),
+            serde_json::json!({ "id": "2", "title": "Heat", "year": 1995 }),
+            serde_json::json!({ "id": "3", "title": "Alien", "rating": 8.5 }),
+        ];
+
+        let json = serde_json::to_string(&expected).unwrap();
+        let jsonl: String = expected.iter().map(|doc| format!("{}\n", doc)).collect();
+        let csv = "id,title,year,rating\n1,Up,2009,\n2,Heat,1995,\n3,Alien,,8.5\n";
+
+        let mut gzip = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        std::io::Write::write_all(&mut gzip, jsonl.as_bytes()).unwrap();
+
+        let files = vec![
+            ("docs.json", json.into_bytes()),
+            ("lines.json", jsonl.clone().into_bytes()),
+            ("docs.ndjson", jsonl.clone().into_bytes()),
+            ("docs.csv", csv.as_bytes().to_vec()),
+            ("docs.jsonl.gz", gzip.finish().unwrap()),
+            (
+                "docs.jsonl.zst",
+                zstd::encode_all(jsonl.as_bytes(), 0).unwrap(),
+            ),
+        ];
+
+        for (name, data) in files {
+            let path = dir.join(name);
+            std::fs::write(&path, data).unwrap();
+
+            let batches = read_all(path.to_str().unwrap(), 2).await;
+            assert_eq!(
+                batches.iter().map(Vec::len).collect::<Vec<_>>(),
+                vec![2, 1],
+                "{}",
+                name
+            );
+            assert_eq!(batches.concat(), expected, "{}", name);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,411 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::anyhow;
+use reqwest::{Method, RequestBuilder};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::fs;
+
+use crate::dataset::Documents;
+use crate::sampler::SamplerHandle;
+use crate::shared::{
+    expect_array,
+    expect_duration,
+    ClientOptions,
+    ParsedResponse,
+    Query,
+    RequestClient,
+    SearchResult,
+    TargetUri,
+};
+
+/// The configuration describing how to talk to a generic HTTP search
+/// service.
+///
+/// Any string in the templates can contain the `{address}` and `{index}`
+/// placeholders, search templates can additionally contain `{query}` which
+/// is percent encoded when it is part of the url.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct GenericConfig {
+    /// The request sent for each search.
+    search: RequestTemplate,
+
+    /// The JSON pointer to the array of hits in the search response,
+    /// e.g. `/data/hits`.
+    ///
+    /// This is required to parse the search responses.
+    hits_pointer: Option<String>,
+
+    /// The JSON pointer to the time in milliseconds the server reports it
+    /// spent processing the search, e.g. `/took`.
+    server_time_pointer: Option<String>,
+
+    /// The optional steps ran before the benchmark starts.
+    #[serde(default)]
+    prep: PrepConfig,
+}
+
+fn default_success_status() -> Vec<u16> {
+    vec![200]
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PrepConfig {
+    /// Creates the index, this is sent before any documents are uploaded.
+    create: Option<RequestTemplate>,
+
+    /// Uploads the documents, each batch of the dataset is sent as a JSON
+    /// array body.
+    upload: Option<RequestTemplate>,
+
+    /// Commits or refreshes the index once the documents are uploaded.
+    commit: Option<RequestTemplate>,
+
+    /// Gets the number of documents in the index to check every document
+    /// was indexed, this requires the `count_pointer`.
+    count: Option<RequestTemplate>,
+
+    /// The JSON pointer to the number of documents in the count response,
+    /// e.g. `/numberOfDocuments`.
+    count_pointer: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RequestTemplate {
+    url: String,
+
+    #[serde(default = "default_method")]
+    method: String,
+
+    #[serde(default)]
+    headers: HashMap<String, String>,
+
+    /// The query string parameters, these are url encoded when sent.
+    #[serde(default)]
+    query: HashMap<String, String>,
+
+    /// The JSON body, placeholders are only replaced within string values.
+    body: Option<Value>,
+
+    /// The status codes which count as the request succeeding.
+    #[serde(default = "default_success_status")]
+    success_status: Vec<u16>,
+}
+
+fn default_method() -> String {
+    "GET".to_string()
+}
+
+/// The values substituted into the request templates.
+struct Placeholders<'a> {
+    address: &'a str,
+    index: &'a str,
+    query: &'a str,
+}
+
+impl<'a> Placeholders<'a> {
+    fn apply(&self, template: &str) -> String {
+        template
+            .replace("{address}", self.address)
+            .replace("{index}", self.index)
+            .replace("{query}", self.query)
+    }
+
+    /// Applies the placeholders to a url, the query is percent encoded so
+    /// characters like `&`, `#` and spaces stay part of the query.
+    fn apply_url(&self, template: &str) -> String {
+        template
+            .replace("{address}", self.address)
+            .replace("{index}", self.index)
+            .replace("{query}", &percent_encode(self.query))
+    }
+
+    fn apply_value(&self, template: &Value) -> Value {
+        match template {
+            Value::String(s) => Value::String(self.apply(s)),
+            Value::Array(values) => {
+                Value::Array(values.iter().map(|v| self.apply_value(v)).collect())
+            },
+            Value::Object(map) => Value::Object(
+                map.iter()
+                    .map(|(k, v)| (k.clone(), self.apply_value(v)))
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+}
+
+/// Percent encodes every byte other than the unreserved url characters.
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+
+    encoded
+}
+
+impl RequestTemplate {
+    fn build(
+        &self,
+        client: &RequestClient,
+        placeholders: &Placeholders,
+    ) -> anyhow::Result<RequestBuilder> {
+        let method = Method::from_str(&self.method.to_uppercase())?;
+        let mut builder = client.request(method, placeholders.apply_url(&self.url));
+
+        for (key, value) in self.headers.iter() {
+            builder = builder.header(key.as_str(), placeholders.apply(value));
+        }
+
+        if !self.query.is_empty() {
+            let query: Vec<(&str, String)> = self
+                .query
+                .iter()
+                .map(|(k, v)| (k.as_str(), placeholders.apply(v)))
+                .collect();
+
+            builder = builder.query(&query);
+        }
+
+        if let Some(body) = self.body.as_ref() {
+            builder = builder.json(&placeholders.apply_value(body));
+        }
+
+        Ok(builder)
+    }
+}
+
+impl GenericConfig {
+    /// If the search responses can be parsed with this config.
+    pub(crate) fn can_parse_responses(&self) -> bool {
+        self.hits_pointer.is_some()
+    }
+}
+
+/// Loads the generic target configuration from the given JSON file.
+pub(crate) async fn load_config(path: &str) -> anyhow::Result<GenericConfig> {
+    let data = fs::read_to_string(path).await?;
+    Ok(serde_json::from_str(&data)?)
+}
+
+pub(crate) async fn prep(
+    address: &str,
+    documents: &mut Documents,
+    index: &str,
+    config: &GenericConfig,
+) -> anyhow::Result<()> {
+    let client = RequestClient::new(ClientOptions::default());
+    let placeholders = Placeholders {
+        address,
+        index,
+        query: "",
+    };
+
+    if let Some(create) = config.prep.create.as_ref() {
+        run_step("create", create.build(&client, &placeholders)?, create).await?;
+    }
+
+    let start = Instant::now();
+    if let Some(upload) = config.prep.upload.as_ref() {
+        while let Some(batch) = documents.next_batch().await? {
+            let builder = upload.build(&client, &placeholders)?.json(&batch);
+            run_step("upload", builder, upload).await?;
+        }
+    }
+
+    if let Some(commit) = config.prep.commit.as_ref() {
+        run_step("commit", commit.build(&client, &placeholders)?, commit).await?;
+    }
+
+    info!(
+        "generic target took {:?} to process {} submitted documents",
+        start.elapsed(),
+        documents.read(),
+    );
+
+    Ok(())
+}
+
+/// Gets the number of documents in the index, this is `None` if the
+/// config doesn't describe how to count them.
+pub(crate) async fn count(
+    address: &str,
+    index: &str,
+    config: &GenericConfig,
+) -> anyhow::Result<Option<usize>> {
+    let prep = &config.prep;
+    let (count, pointer) = match (prep.count.as_ref(), prep.count_pointer.as_deref()) {
+        (Some(count), Some(pointer)) => (count, pointer),
+        (None, None) => return Ok(None),
+        _ => {
+            return Err(anyhow!(
+                "the count prep step requires both the request and the count pointer"
+            ))
+        },
+    };
+
+    let client = RequestClient::new(ClientOptions::default());
+    let placeholders = Placeholders {
+        address,
+        index,
+        query: "",
+    };
+
+    let r = count.build(&client, &placeholders)?.send().await?;
+    let status = r.status().as_u16();
+    if !count.success_status.contains(&status) {
+        return Err(anyhow!(
+            "prep step \"count\" got unexpected response code {} data: {}",
+            status,
+            r.text().await?
+        ));
+    }
+
+    let data: Value = r.json().await?;
+    let count = data
+        .pointer(pointer)
+        .and_then(Value::as_u64)
+        .ok_or_else(|| anyhow!("missing {:?} number in the count response", pointer))?;
+
+    Ok(Some(count as usize))
+}
+
+async fn run_step(
+    name: &str,
+    builder: RequestBuilder,
+    template: &RequestTemplate,
+) -> anyhow::Result<()> {
+    let r = builder.send().await?;
+
+    let status = r.status().as_u16();
+    if !template.success_status.contains(&status) {
+        return Err(anyhow!(
+            "prep step {:?} got unexpected response code {} data: {}",
+            name,
+            status,
+            r.text().await?
+        ));
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn bench_standard(
+    address: Arc<String>,
+    sample: SamplerHandle,
+    terms: Vec<String>,
+    index: String,
+    options: ClientOptions,
+    config: Arc<GenericConfig>,
+) -> anyhow::Result<()> {
+    let addr = address.clone();
+    let index_name = Arc::new(index.clone());
+    crate::shared::start_standard(
+        address,
+        sample,
+        terms,
+        &index,
+        options,
+        move |client, _uri, query| {
+            search(client, addr.clone(), index_name.clone(), config.clone(), query)
+        },
+    )
+    .await
+}
+
+pub(crate) async fn bench_typing(
+    address: Arc<String>,
+    sample: SamplerHandle,
+    terms: Vec<String>,
+    index: String,
+    options: ClientOptions,
+    config: Arc<GenericConfig>,
+) -> anyhow::Result<()> {
+    let addr = address.clone();
+    let index_name = Arc::new(index.clone());
+    crate::shared::start_typing(
+        address,
+        sample,
+        terms,
+        &index,
+        options,
+        move |client, _uri, query| {
+            search(client, addr.clone(), index_name.clone(), config.clone(), query)
+        },
+    )
+    .await
+}
+
+async fn search(
+    client: RequestClient,
+    address: TargetUri,
+    index: Arc<String>,
+    config: Arc<GenericConfig>,
+    query: Query,
+) -> anyhow::Result<SearchResult> {
+    let placeholders = Placeholders {
+        address: &address,
+        index: &index,
+        query: &query,
+    };
+
+    let request = config.search.build(&client, &placeholders)?;
+
+    client
+        .send_search(request, &config.search.success_status, |data| {
+            let pointer = config
+                .hits_pointer
+                .as_deref()
+                .ok_or_else(|| "no hits pointer configured".to_string())?;
+
+            let hits = expect_array(data, pointer)?;
+            let server_time = match config.server_time_pointer.as_deref() {
+                Some(pointer) => Some(expect_duration(data, pointer, 0.001)?),
+                None => None,
+            };
+
+            Ok(ParsedResponse {
+                hits: hits.len(),
+                server_time,
+            })
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_the_query_in_urls() {
+        let placeholders = Placeholders {
+            address: "http://127.0.0.1:7700",
+            index: "movies",
+            query: "fast & furious #7?",
+        };
+
+        assert_eq!(
+            placeholders.apply_url("{address}/indexes/{index}/search?q={query}"),
+            "http://127.0.0.1:7700/indexes/movies/search?q=fast%20%26%20furious%20%237%3F"
+        );
+        assert_eq!(placeholders.apply("{query}"), "fast & furious #7?");
+
+        let config = serde_json::json!({
+            "search": { "url": "{address}/search" },
+            "hit_pointer": "/hits",
+        });
+        assert!(serde_json::from_value::<GenericConfig>(config).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+use std::fmt;
+use std::ops::Deref;
+use std::time::Duration;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use hdrhistogram::serialization::{Deserializer as HdrDeserializer, Serializer as _, V2Serializer};
+use hdrhistogram::Histogram as HdrHistogram;
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// A latency histogram recorded in microseconds.
+///
+/// This serializes to the base64 encoded HdrHistogram V2 format so saved
+/// runs can be loaded back and merged losslessly.
+#[derive(Clone)]
+pub(crate) struct LatencyHistogram(HdrHistogram<u64>);
+
+impl LatencyHistogram {
+    pub(crate) fn new() -> Self {
+        let mut hist = HdrHistogram::<u64>::new_with_bounds(1, 60 * 60 * 1000, 2).unwrap();
+        hist.auto(true);
+
+        Self(hist)
+    }
+
+    /// Records the duration in microseconds.
+    pub(crate) fn record(&mut self, dur: Duration) {
+        self.0.saturating_record((dur.as_micros() as u64).max(1));
+    }
+
+    pub(crate) fn add(&mut self, other: &Self) -> anyhow::Result<()> {
+        self.0.add(&other.0)?;
+        Ok(())
+    }
+
+    /// The latency at the given percentile.
+    pub(crate) fn percentile(&self, percentile: f64) -> Duration {
+        Duration::from_micros(self.0.value_at_percentile(percentile))
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for LatencyHistogram {
+    type Target = HdrHistogram<u64>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Serialize for LatencyHistogram {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut buffer = vec![];
+        V2Serializer::new()
+            .serialize(&self.0, &mut buffer)
+            .map_err(|e| serde::ser::Error::custom(format!("{:?}", e)))?;
+
+        serializer.serialize_str(&STANDARD.encode(buffer))
+    }
+}
+
+impl<'de> Deserialize<'de> for LatencyHistogram {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_str(HistogramVisitor)
+    }
+}
+
+struct HistogramVisitor;
+
+impl<'de> Visitor<'de> for HistogramVisitor {
+    type Value = LatencyHistogram;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a base64 encoded V2 histogram")
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        let buffer = STANDARD.decode(v).map_err(E::custom)?;
+        let mut hist: HdrHistogram<u64> = HdrDeserializer::new()
+            .deserialize(&mut buffer.as_slice())
+            .map_err(|e| E::custom(format!("{:?}", e)))?;
+        hist.auto(true);
+
+        Ok(LatencyHistogram(hist))
+    }
+}
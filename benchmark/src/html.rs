@@ -0,0 +1,325 @@
+use std::fmt::Write;
+use std::time::Duration;
+
+use tokio::fs;
+
+use crate::charts::{self, ChartOptions};
+use crate::histogram::LatencyHistogram;
+use crate::resources::ResourceSample;
+use crate::summary::RunSummary;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em auto; max-width: 1200px; color: #222; }
+h1 { border-bottom: 2px solid #ddd; padding-bottom: 0.3em; }
+table { border-collapse: collapse; margin-bottom: 1.5em; }
+th, td { border: 1px solid #ddd; padding: 0.35em 0.8em; text-align: left; }
+th { background: #f4f4f4; }
+td.num { text-align: right; font-family: monospace; }
+figure { margin: 0 0 2em 0; }
+figure svg { width: 100%; height: auto; }
+";
+
+/// Writes a standalone HTML report of the run to `report.html` in the
+/// output directory, the charts are inlined as SVGs so the file can be
+/// shared on its own.
+pub(crate) async fn write(
+    output: &str,
+    summary: &RunSummary,
+    resources: &[ResourceSample],
+    options: &ChartOptions,
+) -> anyhow::Result<()> {
+    let html = render(summary, resources, options)?;
+
+    let path = format!("{}/report.html", output);
+    fs::write(&path, html).await?;
+    info!("HTML report has been saved to {}", path);
+
+    Ok(())
+}
+
+fn render(
+    summary: &RunSummary,
+    resources: &[ResourceSample],
+    options: &ChartOptions,
+) -> anyhow::Result<String> {
+    let mut out = String::new();
+
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(
+        out,
+        "<html><head><meta charset=\"utf-8\"><title>Benchmark Report</title>"
+    )?;
+    writeln!(out, "<style>{}</style></head><body>", STYLE)?;
+    writeln!(out, "<h1>Benchmark Report</h1>")?;
+
+    if !summary.config.is_empty() {
+        writeln!(out, "<h2>Configuration</h2><table>")?;
+        for (name, value) in summary.config.iter() {
+            writeln!(
+                out,
+                "<tr><th>{}</th><td>{}</td></tr>",
+                escape(name),
+                escape(value)
+            )?;
+        }
+        writeln!(out, "</table>")?;
+    }
+
+    write_summary(&mut out, summary)?;
+    write_percentiles(&mut out, summary, options)?;
+    write_errors(&mut out, summary)?;
+    write_slowest_queries(&mut out, summary)?;
+    write_charts(&mut out, summary, resources, options)?;
+
+    writeln!(out, "</body></html>")?;
+
+    Ok(out)
+}
+
+fn write_summary(out: &mut String, summary: &RunSummary) -> anyhow::Result<()> {
+    let hist = &summary.latencies;
+    let mut rows = vec![
+        ("Successful Requests", hist.len().to_string()),
+        ("Requests/sec", format!("{:.2}", summary.requests_second)),
+        (
+            "Average Latency",
+            format!("{:?}", Duration::from_secs_f64(hist.mean() / 1_000_000.0)),
+        ),
+        (
+            "Min Latency",
+            format!("{:?}", Duration::from_micros(hist.min())),
+        ),
+        (
+            "Max Latency",
+            format!("{:?}", Duration::from_micros(hist.max())),
+        ),
+        (
+            "Stdev Latency",
+            format!("{:?}", Duration::from_secs_f64(hist.stdev() / 1_000_000.0)),
+        ),
+        (
+            "Failed Requests",
+            summary.errors.values().sum::<usize>().to_string(),
+        ),
+    ];
+
+    let invalid_total = summary.invalid_responses.values().sum::<usize>();
+    if summary.valid_responses + invalid_total > 0 {
+        let valid = summary.valid_responses.max(1) as f64;
+        rows.push(("Valid Responses", summary.valid_responses.to_string()));
+        rows.push((
+            "Zero Result Rate",
+            format!("{:.2}%", summary.zero_results as f64 / valid * 100.0),
+        ));
+        rows.push((
+            "Average Hits",
+            format!("{:.2}", summary.total_hits as f64 / valid),
+        ));
+        rows.push(("Schema Validation Failures", invalid_total.to_string()));
+    }
+
+    writeln!(out, "<h2>Summary</h2><table>")?;
+    for (name, value) in rows {
+        writeln!(
+            out,
+            "<tr><th>{}</th><td class=\"num\">{}</td></tr>",
+            name, value
+        )?;
+    }
+    writeln!(out, "</table>")?;
+
+    Ok(())
+}
+
+fn write_percentiles(
+    out: &mut String,
+    summary: &RunSummary,
+    options: &ChartOptions,
+) -> anyhow::Result<()> {
+    let mut rows: Vec<(String, &LatencyHistogram)> =
+        vec![("All Successful".to_string(), &summary.latencies)];
+
+    let mut statuses: Vec<_> = summary.status_latencies.iter().collect();
+    statuses.sort_by_key(|(status, _)| **status);
+    for (status, hist) in statuses {
+        rows.push((format!("Status {}", status), hist));
+    }
+
+    if !summary.server_latencies.is_empty() {
+        rows.push(("Server".to_string(), &summary.server_latencies));
+        rows.push(("Overhead".to_string(), &summary.overhead_latencies));
+    }
+
+    if !summary.phases.ttfb.is_empty() {
+        for (name, hist, _) in summary.phase_series().iter() {
+            if !hist.is_empty() {
+                rows.push((format!("Phase: {}", name), *hist));
+            }
+        }
+    }
+
+    writeln!(
+        out,
+        "<h2>Latency Percentiles</h2><table><tr><th>Requests</th><th>Count</th>"
+    )?;
+    for p in options.percentiles.iter() {
+        write!(out, "<th>p{}</th>", p)?;
+    }
+    writeln!(out, "</tr>")?;
+
+    for (name, hist) in rows {
+        write!(
+            out,
+            "<tr><th>{}</th><td class=\"num\">{}</td>",
+            escape(&name),
+            hist.len()
+        )?;
+        for p in options.percentiles.iter() {
+            write!(out, "<td class=\"num\">{:?}</td>", hist.percentile(*p))?;
+        }
+        writeln!(out, "</tr>")?;
+    }
+    writeln!(out, "</table>")?;
+
+    Ok(())
+}
+
+fn write_errors(out: &mut String, summary: &RunSummary) -> anyhow::Result<()> {
+    if summary.errors.is_empty() && summary.invalid_responses.is_empty() {
+        return Ok(());
+    }
+
+    let total = summary
+        .status_latencies
+        .values()
+        .map(|hist| hist.len())
+        .sum::<u64>()
+        .max(1) as f64;
+
+    writeln!(out, "<h2>Errors</h2>")?;
+
+    if !summary.errors.is_empty() {
+        let mut errors: Vec<_> = summary.errors.iter().collect();
+        errors.sort_by_key(|(status, _)| **status);
+
+        writeln!(
+            out,
+            "<table><tr><th>Status</th><th>Count</th><th>Of All Requests</th></tr>"
+        )?;
+        for (status, count) in errors {
+            writeln!(
+                out,
+                "<tr><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{:.2}%</td></tr>",
+                status,
+                count,
+                *count as f64 / total * 100.0
+            )?;
+        }
+        writeln!(out, "</table>")?;
+    }
+
+    if !summary.invalid_responses.is_empty() {
+        let mut reasons: Vec<_> = summary.invalid_responses.iter().collect();
+        reasons.sort_by(|a, b| b.1.cmp(a.1));
+
+        writeln!(
+            out,
+            "<table><tr><th>Invalid Response</th><th>Count</th></tr>"
+        )?;
+        for (reason, count) in reasons {
+            writeln!(
+                out,
+                "<tr><td>{}</td><td class=\"num\">{}</td></tr>",
+                escape(reason),
+                count
+            )?;
+        }
+        writeln!(out, "</table>")?;
+    }
+
+    Ok(())
+}
+
+fn write_slowest_queries(out: &mut String, summary: &RunSummary) -> anyhow::Result<()> {
+    if summary.slowest_queries.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(out, "<h2>Slowest Queries</h2>")?;
+    writeln!(
+        out,
+        "<table><tr><th>#</th><th>Query</th><th>Status</th><th>Latency</th></tr>"
+    )?;
+    for (i, query) in summary.slowest_queries.iter().enumerate() {
+        writeln!(
+            out,
+            "<tr><td class=\"num\">{}</td><td>{}</td><td>{}</td><td class=\"num\">{:?}</td></tr>",
+            i + 1,
+            escape(&query.query),
+            query.status,
+            Duration::from_micros(query.latency_micros)
+        )?;
+    }
+    writeln!(out, "</table>")?;
+
+    Ok(())
+}
+
+fn write_charts(
+    out: &mut String,
+    summary: &RunSummary,
+    resources: &[ResourceSample],
+    options: &ChartOptions,
+) -> anyhow::Result<()> {
+    let mut svgs = vec![charts::render_svg(options, |root| {
+        charts::percentile_lines(root, options, "Request Latency", &summary.latency_series())
+    })?];
+
+    if summary.sentence_length_latencies.len() > 1 {
+        svgs.push(charts::render_svg(options, |root| {
+            charts::sentence_lengths(root, &summary.sentence_length_latencies)
+        })?);
+    }
+
+    if !summary.server_latencies.is_empty() {
+        svgs.push(charts::render_svg(options, |root| {
+            charts::percentile_lines(
+                root,
+                options,
+                "Server vs Client Latency",
+                &summary.breakdown_series(),
+            )
+        })?);
+    }
+
+    if !summary.phases.ttfb.is_empty() {
+        svgs.push(charts::render_svg(options, |root| {
+            charts::percentile_lines(
+                root,
+                options,
+                "Request Phase Latency",
+                &summary.phase_series(),
+            )
+        })?);
+    }
+
+    if !resources.is_empty() {
+        svgs.push(charts::render_svg(options, |root| {
+            charts::resource_timeline(root, resources)
+        })?);
+    }
+
+    writeln!(out, "<h2>Charts</h2>")?;
+    for svg in svgs {
+        writeln!(out, "<figure>{}</figure>", svg)?;
+    }
+
+    Ok(())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,264 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use anyhow::anyhow;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::charts::{self, ChartOptions, TradeoffPoint};
+use crate::scenario::{ScenarioResult, SweepOptions};
+use crate::sweep;
+use crate::{BenchTarget, Context};
+
+/// The results of a run with one combination of the index settings.
+#[derive(Debug, Serialize)]
+struct SettingsResult {
+    /// The swept settings this run used.
+    settings: Map<String, Value>,
+
+    #[serde(flatten)]
+    result: ScenarioResult,
+
+    /// If no other run had both a lower p99 latency and a higher throughput.
+    pareto_optimal: bool,
+
+    /// The throughput relative to the best throughput multiplied by the best
+    /// p99 latency relative to this run's, higher is better.
+    score: f64,
+}
+
+/// Recreates the lnx index with every combination of the swept settings,
+/// re-ingests the documents and runs the same workload against each.
+///
+/// The settings which give the best trade-off between the p99 latency and
+/// throughput are reported along with every other run which wasn't beaten
+/// on both.
+pub(crate) fn run(
+    base: Context,
+    sweep: &SweepOptions,
+    grid: &BTreeMap<String, Vec<Value>>,
+) -> anyhow::Result<Vec<ScenarioResult>> {
+    if !matches!(base.target, BenchTarget::Lnx) {
+        return Err(anyhow!("index settings can only be swept on the lnx target"));
+    }
+    if base.no_prep {
+        return Err(anyhow!("index settings can't be swept without prep as the index must be recreated"));
+    }
+    let has_schema = base.index_schema.is_some() || base.infer_schema;
+    let base_settings = match base.index_settings.as_ref() {
+        Some(Value::Object(settings)) => settings.clone(),
+        None if has_schema => Map::new(),
+        _ => {
+            return Err(anyhow!(
+                "sweeping the index settings requires the base index settings or an index schema to create the index from"
+            ))
+        },
+    };
+
+    let combinations = combinations(grid)?;
+    let is_matrix = sweep.concurrency.is_some() || sweep.threads.is_some() || sweep.mode.is_some();
+
+    let mut results = vec![];
+    for (i, swept) in combinations.iter().enumerate() {
+        let name = settings_name(swept);
+        let output = format!("{}/{}", base.output, name);
+        fs::create_dir_all(&output)?;
+
+        info!("running index settings {} ({}/{})", name, i + 1, combinations.len());
+
+        let mut settings = base_settings.clone();
+        settings.extend(swept.clone());
+        let ctx = Context {
+            index_settings: Some(Value::Object(settings)),
+            raw_log: base.raw_log.as_ref().map(|_| format!("{}/raw-log.csv", output)),
+            output,
+            ..base.clone()
+        };
+
+        let cells = if is_matrix {
+            sweep::run_matrix(ctx, sweep)?
+        } else {
+            let mut result = ScenarioResult::new(String::new(), &ctx);
+            let summary = crate::run_summarised(ctx)
+                .map_err(|e| anyhow!("index settings {} failed: {}", name, e))?;
+            result.record(&summary);
+            vec![result]
+        };
+
+        for mut result in cells {
+            result.name = if result.name.is_empty() {
+                name.clone()
+            } else {
+                format!("{}/{}", name, result.name)
+            };
+            results.push((swept.clone(), result));
+        }
+    }
+
+    let results = score(results);
+    log_results(&results);
+
+    let data = format!("{}/index-settings.json", base.output);
+    fs::write(&data, serde_json::to_vec_pretty(&results)?)?;
+    info!("Index settings results have been saved to {}", data);
+
+    let points: Vec<TradeoffPoint> = results
+        .iter()
+        .enumerate()
+        .map(|(i, r)| TradeoffPoint {
+            label: format!("#{}", i + 1),
+            latency_ms: r.result.p99_latency_ms,
+            requests_second: r.result.requests_second,
+            pareto_optimal: r.pareto_optimal,
+        })
+        .collect();
+    let chart = format!("{}/index-settings-tradeoff.png", base.output);
+    charts::save_png(&chart, &ChartOptions::default(), |root| {
+        charts::tradeoff(root, "Index Settings Latency vs Throughput", &points)
+    })?;
+    info!("Index settings trade-off has been saved to {}", chart);
+
+    Ok(results.into_iter().map(|r| r.result).collect())
+}
+
+/// Every combination of the swept values.
+fn combinations(grid: &BTreeMap<String, Vec<Value>>) -> anyhow::Result<Vec<Map<String, Value>>> {
+    let mut combinations = vec![Map::new()];
+
+    for (key, values) in grid.iter() {
+        if values.is_empty() {
+            return Err(anyhow!("the swept index setting {:?} has no values", key));
+        }
+
+        combinations = combinations
+            .into_iter()
+            .flat_map(|settings| {
+                values.iter().map(move |value| {
+                    let mut settings = settings.clone();
+                    settings.insert(key.clone(), value.clone());
+                    settings
+                })
+            })
+            .collect();
+    }
+
+    Ok(combinations)
+}
+
+/// A directory friendly name of the swept settings, e.g.
+/// `reader_threads-2_use_fast_fuzzy-true`.
+fn settings_name(settings: &Map<String, Value>) -> String {
+    settings
+        .iter()
+        .map(|(key, value)| {
+            let value = match value {
+                Value::String(v) => v.clone(),
+                other => other.to_string(),
+            };
+            let value: String = value
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() || c == '.' { c } else { '-' })
+                .collect();
+
+            format!("{}-{}", key, value)
+        })
+        .collect::<Vec<String>>()
+        .join("_")
+}
+
+fn score(results: Vec<(Map<String, Value>, ScenarioResult)>) -> Vec<SettingsResult> {
+    let best_throughput = results
+        .iter()
+        .map(|(_, r)| r.requests_second)
+        .fold(0.0, f64::max);
+    let best_latency = results
+        .iter()
+        .map(|(_, r)| r.p99_latency_ms)
+        .filter(|v| *v > 0.0)
+        .fold(f64::INFINITY, f64::min);
+
+    let dominated = |r: &ScenarioResult| {
+        results.iter().any(|(_, other)| {
+            other.p99_latency_ms <= r.p99_latency_ms
+                && other.requests_second >= r.requests_second
+                && (other.p99_latency_ms < r.p99_latency_ms || other.requests_second > r.requests_second)
+        })
+    };
+
+    let scored: Vec<(bool, f64)> = results
+        .iter()
+        .map(|(_, r)| {
+            let score = if best_throughput > 0.0 && r.p99_latency_ms > 0.0 {
+                (r.requests_second / best_throughput) * (best_latency / r.p99_latency_ms)
+            } else {
+                0.0
+            };
+
+            (!dominated(r), score)
+        })
+        .collect();
+
+    results
+        .into_iter()
+        .zip(scored)
+        .map(|((settings, result), (pareto_optimal, score))| SettingsResult {
+            settings,
+            result,
+            pareto_optimal,
+            score,
+        })
+        .collect()
+}
+
+fn log_results(results: &[SettingsResult]) {
+    info!("Index settings results:");
+    for (i, r) in results.iter().enumerate() {
+        info!(
+            "     #{} {}: {:.2} req/s, p99={:.2}ms, score={:.3}{}",
+            i + 1,
+            r.result.name,
+            r.result.requests_second,
+            r.result.p99_latency_ms,
+            r.score,
+            if r.pareto_optimal { " (pareto optimal)" } else { "" },
+        );
+    }
+
+    // The best score is never beaten on both latency and throughput so it is
+    // always one of the pareto optimal settings.
+    let best = results
+        .iter()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.score.total_cmp(&b.score));
+    if let Some((i, best)) = best {
+        info!(
+            "     Best Trade-off: #{} {} ({})",
+            i + 1,
+            best.result.name,
+            Value::Object(best.settings.clone()),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_combination() {
+        let grid: BTreeMap<String, Vec<Value>> = serde_json::from_value(serde_json::json!({
+            "reader_threads": [1, 2],
+            "use_fast_fuzzy": [true, false],
+        }))
+        .unwrap();
+
+        let names: Vec<String> = combinations(&grid).unwrap().iter().map(settings_name).collect();
+
+        assert_eq!(names, vec![
+            "reader_threads-1_use_fast_fuzzy-true",
+            "reader_threads-1_use_fast_fuzzy-false",
+            "reader_threads-2_use_fast_fuzzy-true",
+            "reader_threads-2_use_fast_fuzzy-false",
+        ]);
+    }
+}
@@ -1,29 +1,66 @@
 #[macro_use]
 extern crate log;
 
+mod charts;
+mod checkpoint;
+mod dataset;
+mod generic;
+mod histogram;
+mod html;
+mod index_sweep;
+mod live;
 mod lnx;
 mod meilisearch;
+mod metrics;
+mod raw_log;
+mod report;
+mod resources;
 mod sampler;
+mod scenario;
+mod schema;
 mod shared;
+mod summary;
+mod sweep;
+mod synthetic;
+mod timing;
 mod typesense;
 
+use std::net::SocketAddr;
+use std::path::Path;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use rand::seq::SliceRandom;
-use serde_json::Value;
+use serde::Deserialize;
+use serde_json::{Map, Value};
 use tokio::fs;
 use tokio::task::JoinHandle;
 
-/// The two benchmarking targets.
+use crate::checkpoint::Checkpoint;
+use crate::dataset::Documents;
+use crate::schema::IndexSchema;
+use crate::shared::ClientOptions;
+use crate::summary::RunSummary;
+
+pub use crate::charts::{ChartOptions, DEFAULT_PERCENTILES};
+pub use crate::scenario::{run_scenarios, BenchOptions, SweepOptions};
+pub use crate::shared::typing_prefixes;
+pub use crate::synthetic::{parse_count, FieldSpec};
+pub use crate::typesense::query_by as typesense_query_by;
+
+/// The benchmarking targets.
 ///
-/// This was designed to compare MeiliSearch and lnx.
-#[derive(Debug, Copy, Clone)]
+/// This was designed to compare MeiliSearch and lnx, the generic target
+/// allows any other HTTP search service to be described by a config file.
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum BenchTarget {
     MeiliSearch,
     Lnx,
     TypeSense,
+    Generic,
 }
 
 impl FromStr for BenchTarget {
@@ -34,8 +71,9 @@ impl FromStr for BenchTarget {
             "meilisearch" => Ok(Self::MeiliSearch),
             "typesense" => Ok(Self::TypeSense),
             "lnx" => Ok(Self::Lnx),
+            "generic" => Ok(Self::Generic),
             other => Err(format!(
-                "unknown target type got {:?}, expected either 'meilisearch' or 'lnx'",
+                "unknown target type got {:?}, expected one of 'meilisearch', 'typesense', 'lnx' or 'generic'",
                 other,
             )),
         }
@@ -44,7 +82,8 @@ impl FromStr for BenchTarget {
 
 /// The benchmark type to run, this allows us to have several
 /// modes and areas to test.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum BenchMode {
     /// Simulates typing our a sentence / word a character at a time.
     Typing,
@@ -68,6 +107,10 @@ impl FromStr for BenchMode {
     }
 }
 
+/// How long the index has to hold every document after the upload.
+const VERIFY_COUNT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone)]
 pub struct Context {
     pub address: String,
     pub data_file: String,
@@ -78,10 +121,148 @@ pub struct Context {
     pub output: String,
     pub search_terms: String,
     pub no_prep: bool,
+
+    /// Don't check the index holds every document in the data file after
+    /// the documents have been uploaded.
+    pub no_verify: bool,
     pub index: String,
+
+    /// How long to keep searching for, the search terms are repeated until
+    /// this has passed. If not set each worker searches every term once.
+    pub duration: Option<Duration>,
+
+    /// The number of documents uploaded in each request, the data file is
+    /// streamed so only a few batches are held in memory at once.
+    pub batch_size: usize,
+
+    /// The number of batches uploaded to lnx at once.
+    pub upload_concurrency: usize,
+
+    /// The file to save the progress of the lnx upload to, an interrupted
+    /// upload is resumed from it rather than started over.
+    pub upload_checkpoint: Option<String>,
+
+    /// The settings to create or update the index with before uploading
+    /// the documents.
+    pub index_settings: Option<Value>,
+
+    /// The lnx index schema to create the index with, an object of the
+    /// `fields` and optionally the `search_fields`.
+    pub index_schema: Option<Value>,
+
+    /// Infer the lnx index or TypeSense collection schema from the documents
+    /// and create the index with it.
+    pub infer_schema: bool,
+
+    /// The MeiliSearch master or API key sent with every request.
+    pub api_key: Option<String>,
+
+    /// The fields TypeSense searches, if not set every string field of the
+    /// collection is searched.
+    pub query_by: Option<Vec<String>>,
+
+    /// Keep the index the benchmark created rather than deleting it once
+    /// the benchmark has finished.
+    pub keep_index: bool,
+
+    /// The path to the JSON config describing the generic target.
+    pub target_config: Option<String>,
+
+    /// Parse and validate the search response bodies.
+    pub parse_responses: bool,
+
+    /// Record the time spent in each phase of the search requests.
+    pub phase_timing: bool,
+
+    /// Open a new connection for every search request.
+    pub new_connection_per_request: bool,
+
+    /// Keep every latency sample and write them out alongside the charts.
+    pub keep_raw_samples: bool,
+
+    /// The path to stream a CSV record of every request to.
+    pub raw_log: Option<String>,
+
+    /// The address to serve the live benchmark metrics on.
+    pub metrics_bind: Option<SocketAddr>,
+
+    /// The pid of a local server process to sample the resource usage of.
+    pub server_pid: Option<u32>,
+
+    /// The name of a local server process to sample the resource usage of,
+    /// this is ignored if `server_pid` is set.
+    pub server_process: Option<String>,
+}
+
+/// The settings for regenerating the stats and charts of a previous run.
+pub struct ReportContext {
+    /// The saved run to report on, either the `run-data.json` written by
+    /// the benchmark or a raw request log CSV.
+    pub input: String,
+
+    /// The directory to write the charts to.
+    pub output: String,
+
+    pub chart_options: ChartOptions,
+
+    /// Only include requests sent at least this long after the first request.
+    ///
+    /// This is only supported by raw request logs.
+    pub since: Option<Duration>,
+
+    /// Only include requests sent up to this long after the first request.
+    ///
+    /// This is only supported by raw request logs.
+    pub until: Option<Duration>,
+}
+
+/// The settings for generating a synthetic dataset and its queries.
+pub struct GenDataContext {
+    /// The number of documents to generate.
+    pub docs: u64,
+
+    /// The fields of each document, an `id` field is always added.
+    pub fields: Vec<FieldSpec>,
+
+    /// The data file to write the documents to.
+    pub output: String,
+
+    /// The file to write the queries to, no queries are generated if unset.
+    pub search_terms: Option<String>,
+
+    /// The number of queries to generate.
+    pub queries: usize,
+
+    /// The number of distinct words the text is made of.
+    pub vocabulary: usize,
+
+    /// The exponent of the Zipf distribution the words are drawn from,
+    /// higher values make the most common words more dominant.
+    pub zipf_exponent: f64,
+
+    /// The random seed, the same seed and settings always generate the
+    /// same dataset.
+    pub seed: u64,
+}
+
+pub fn gen_data(ctx: GenDataContext) -> anyhow::Result<()> {
+    synthetic::generate(ctx)
+}
+
+pub fn report(ctx: ReportContext) -> anyhow::Result<()> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    runtime.block_on(report::generate(ctx))
 }
 
 pub fn run(ctx: Context) -> anyhow::Result<()> {
+    run_summarised(ctx)?;
+    Ok(())
+}
+
+fn run_summarised(ctx: Context) -> anyhow::Result<RunSummary> {
     info!("starting runtime with {} threads", ctx.threads);
     let runtime = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
@@ -91,23 +272,212 @@ pub fn run(ctx: Context) -> anyhow::Result<()> {
     runtime.block_on(start(ctx))
 }
 
-async fn start(ctx: Context) -> anyhow::Result<()> {
-    let mut sample_system = sampler::Sampler::new(ctx.output.clone());
+/// Preps the index once for several runs which share it, e.g. the cells of a
+/// sweep, those runs must then skip the prep.
+///
+/// Returns if the index was created by the prep, it is deleted by
+/// `teardown_shared_index` once every run has finished.
+pub(crate) fn prep_shared_index(ctx: &Context) -> anyhow::Result<bool> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .worker_threads(ctx.threads)
+        .build()?;
+
+    runtime.block_on(async {
+        let generic_config = load_generic_config(ctx).await?;
+        let mut created_index = false;
+        let result = prep(ctx, &mut created_index, generic_config.as_deref()).await;
+        if let Err(e) = result {
+            if created_index {
+                teardown_index(ctx, true).await;
+            }
+            return Err(e);
+        }
+
+        Ok(created_index)
+    })
+}
+
+/// Deletes the index prepped by `prep_shared_index` once every run sharing
+/// it has finished.
+pub(crate) fn teardown_shared_index(
+    ctx: &Context,
+    created_index: bool,
+    failed: bool,
+) -> anyhow::Result<()> {
+    if !created_index {
+        return Ok(());
+    }
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(teardown_index(ctx, failed));
+
+    Ok(())
+}
+
+async fn start(ctx: Context) -> anyhow::Result<RunSummary> {
+    let raw_log = match ctx.raw_log.as_ref() {
+        Some(path) => Some(raw_log::RawLog::create(path).await?),
+        None => None,
+    };
+    let sample_system =
+        sampler::Sampler::new(ctx.output.clone(), ctx.keep_raw_samples, raw_log);
     let ctx = Arc::new(ctx);
+    let generic_config = load_generic_config(&ctx).await?;
+
+    let options = ClientOptions {
+        parse_responses: ctx.parse_responses,
+        phase_timing: ctx.phase_timing,
+        new_connection_per_request: ctx.new_connection_per_request,
+    };
+
+    let mut created_index = false;
+    let result = match prep(&ctx, &mut created_index, generic_config.as_deref()).await {
+        Ok(()) => bench(ctx.clone(), sample_system, generic_config, options).await,
+        Err(e) => Err(e),
+    };
 
+    if created_index {
+        teardown_index(&ctx, result.is_err()).await;
+    }
+
+    result
+}
+
+async fn load_generic_config(ctx: &Context) -> Result<Option<Arc<generic::GenericConfig>>> {
+    let config = match (ctx.target, ctx.target_config.as_ref()) {
+        (BenchTarget::Generic, Some(path)) => Arc::new(generic::load_config(path).await?),
+        (BenchTarget::Generic, None) => {
+            return Err(anyhow!("the generic target requires a target config file"))
+        },
+        _ => return Ok(None),
+    };
+
+    if ctx.parse_responses && !config.can_parse_responses() {
+        return Err(anyhow!(
+            "the generic target config requires a hits pointer to parse responses"
+        ));
+    }
+
+    Ok(Some(config))
+}
+
+/// Creates the index if needed, uploads the documents and verifies they
+/// have all been indexed, nothing is done if prep is skipped.
+///
+/// `created_index` is set as soon as the index has been created so it can
+/// be torn down even if the upload fails.
+async fn prep(
+    ctx: &Context,
+    created_index: &mut bool,
+    generic_config: Option<&generic::GenericConfig>,
+) -> Result<()> {
+    if ctx.no_prep {
+        return Ok(());
+    }
+
+    let mut documents = Documents::open(&ctx.data_file, ctx.batch_size)?;
+    let checkpoint = match ctx.upload_checkpoint.as_deref() {
+        Some(path) if matches!(ctx.target, BenchTarget::Lnx) => {
+            Some(Checkpoint::load(path, &ctx.data_file, &ctx.index, ctx.batch_size)?)
+        },
+        Some(_) => return Err(anyhow!("upload checkpoints are only supported by the lnx target")),
+        None => None,
+    };
+    let resumed = checkpoint.as_ref().map(Checkpoint::is_resumed).unwrap_or_default();
+
+    *created_index = create_index(ctx, &mut documents, resumed).await?;
+
+    let upload = lnx::UploadOptions {
+        concurrency: ctx.upload_concurrency,
+        checkpoint,
+    };
+    let expected = upload_documents(ctx, documents, upload, generic_config).await?;
+    if !ctx.no_verify {
+        verify_count(ctx, expected, generic_config).await?;
+    }
+
+    Ok(())
+}
+
+/// Deletes the index created by the prep unless it is being kept.
+///
+/// An interrupted upload leaves its checkpoint behind and keeps the index
+/// so the next run can resume it.
+async fn teardown_index(ctx: &Context, failed: bool) {
+    let interrupted = ctx
+        .upload_checkpoint
+        .as_ref()
+        .map(|path| failed && Path::new(path).exists())
+        .unwrap_or_default();
+
+    if ctx.keep_index || interrupted {
+        return;
+    }
+
+    let deleted = match ctx.target {
+        BenchTarget::TypeSense => typesense::delete_collection(&ctx.address, &ctx.index).await,
+        _ => lnx::delete_index(&ctx.address, &ctx.index).await,
+    };
+
+    if let Err(e) = deleted {
+        warn!("failed to delete the index {:?} after the benchmark: {}", ctx.index, e);
+    }
+}
+
+async fn bench(
+    ctx: Arc<Context>,
+    mut sample_system: sampler::Sampler,
+    generic_config: Option<Arc<generic::GenericConfig>>,
+    options: ClientOptions,
+) -> anyhow::Result<RunSummary> {
     let target = ctx.target;
     let mode = ctx.mode;
 
-    if !ctx.no_prep {
-        prep_systems(target, &ctx.address, &ctx.data_file, &ctx.index).await?;
-    }
+    let query_by = match (target, ctx.query_by.as_ref()) {
+        (BenchTarget::TypeSense, Some(fields)) => fields.join(","),
+        (BenchTarget::TypeSense, None) => match ctx.index_schema.as_ref() {
+            Some(schema) => IndexSchema::from_value(schema)?.search_fields.join(","),
+            None => typesense::query_by(&ctx.address, &ctx.index)
+                .await
+                .map_err(|e| anyhow!("{}, pass --query-by", e))?,
+        },
+        _ => String::new(),
+    };
+    let query_by = Arc::new(query_by);
+    let api_key = ctx.api_key.clone().map(Arc::new);
     let terms = get_terms(&ctx.search_terms).await?;
+
+    let server_pid = match (ctx.server_pid, ctx.server_process.as_ref()) {
+        (Some(pid), _) => Some(pid),
+        (None, Some(name)) => {
+            let name = name.clone();
+            Some(tokio::task::spawn_blocking(move || resources::find_process(&name)).await??)
+        },
+        (None, None) => None,
+    };
     let address = Arc::new(ctx.address.clone());
 
     info!("Service ready! Beginning benchmark.");
     info!("     Concurrency @ {} clients", ctx.concurrency);
     info!("     Searching @ {} sentences", terms.len());
     info!("     Mode @ {:?}", mode);
+    if let Some(duration) = ctx.duration {
+        info!("     Duration @ {:?}", duration);
+        sample_system.set_deadline(Instant::now() + duration);
+    }
+
+    let progress = live::Progress::start(sample_system.live_stats());
+    let metrics_server = match ctx.metrics_bind {
+        Some(bind) => Some(metrics::serve(bind, sample_system.live_stats())?),
+        None => None,
+    };
+    let resource_monitor = match server_pid {
+        Some(pid) => Some(resources::ResourceMonitor::start(pid, sample_system.live_stats())?),
+        None => None,
+    };
 
     let mut handles = vec![];
     for _ in 0..ctx.concurrency {
@@ -118,27 +488,39 @@ async fn start(ctx: Context) -> anyhow::Result<()> {
         temp_terms.shuffle(&mut rng);
 
         let sample_handler = sample_system.get_handle();
+        let config = generic_config.clone();
+        let query_by = query_by.clone();
+        let api_key = api_key.clone();
 
         let handle: JoinHandle<Result<()>> = tokio::spawn(async move {
             match (target, mode) {
                 (BenchTarget::MeiliSearch, BenchMode::Standard) => {
-                    meilisearch::bench_standard(addr, sample_handler, temp_terms, index).await
+                    meilisearch::bench_standard(addr, sample_handler, temp_terms, index, options, api_key).await
                 },
                 (BenchTarget::MeiliSearch, BenchMode::Typing) => {
-                    meilisearch::bench_typing(addr, sample_handler, temp_terms, index).await
+                    meilisearch::bench_typing(addr, sample_handler, temp_terms, index, options, api_key).await
                 },
                 (BenchTarget::Lnx, BenchMode::Standard) => {
-                    lnx::bench_standard(addr, sample_handler, temp_terms, index).await
+                    lnx::bench_standard(addr, sample_handler, temp_terms, index, options).await
                 },
                 (BenchTarget::Lnx, BenchMode::Typing) => {
-                    lnx::bench_typing(addr, sample_handler, temp_terms, index).await
+                    lnx::bench_typing(addr, sample_handler, temp_terms, index, options).await
                 },
 
                 (BenchTarget::TypeSense, BenchMode::Standard) => {
-                    typesense::bench_standard(addr, sample_handler, temp_terms, index).await
+                    typesense::bench_standard(addr, sample_handler, temp_terms, index, options, query_by).await
                 },
                 (BenchTarget::TypeSense, BenchMode::Typing) => {
-                    typesense::bench_typing(addr, sample_handler, temp_terms, index).await
+                    typesense::bench_typing(addr, sample_handler, temp_terms, index, options, query_by).await
+                },
+
+                (BenchTarget::Generic, BenchMode::Standard) => {
+                    let config = config.ok_or_else(|| anyhow!("missing generic target config"))?;
+                    generic::bench_standard(addr, sample_handler, temp_terms, index, options, config).await
+                },
+                (BenchTarget::Generic, BenchMode::Typing) => {
+                    let config = config.ok_or_else(|| anyhow!("missing generic target config"))?;
+                    generic::bench_typing(addr, sample_handler, temp_terms, index, options, config).await
                 },
             }
         });
@@ -152,18 +534,217 @@ async fn start(ctx: Context) -> anyhow::Result<()> {
         }
     }
 
-    sample_system.wait_and_sample().await?;
+    progress.stop().await;
+    let resource_samples = match resource_monitor {
+        Some(monitor) => monitor.stop().await,
+        None => vec![],
+    };
 
-    Ok(())
+    let summary = sample_system.wait_and_sample(run_config(&ctx, terms.len())).await?;
+    resources::report(&ctx.output, &resource_samples).await?;
+    html::write(&ctx.output, &summary, &resource_samples, &ChartOptions::default()).await?;
+
+    if let Some(server) = metrics_server {
+        server.abort();
+    }
+
+    Ok(summary)
 }
 
-async fn prep_systems(target: BenchTarget, address: &str, path: &str, index: &str) -> Result<()> {
-    let json_data = fs::read_to_string(path).await?;
-    let json_data: Value = serde_json::from_str(&json_data)?;
-    match target {
-        BenchTarget::MeiliSearch => meilisearch::prep(address, json_data, index).await,
-        BenchTarget::Lnx => lnx::prep(address, json_data, index).await,
-        BenchTarget::TypeSense =>  typesense::prep(address, json_data, index).await,
+/// The settings of the run shown in the HTML report.
+fn run_config(ctx: &Context, terms: usize) -> Vec<(String, String)> {
+    let config = vec![
+        ("Target", format!("{:?}", ctx.target)),
+        ("Mode", format!("{:?}", ctx.mode)),
+        ("Address", ctx.address.clone()),
+        ("Index", ctx.index.clone()),
+        ("Concurrency", ctx.concurrency.to_string()),
+        ("Threads", ctx.threads.to_string()),
+        (
+            "Duration",
+            ctx.duration.map(|v| format!("{:?}", v)).unwrap_or_else(|| "one pass".to_string()),
+        ),
+        ("Search Terms", format!("{} ({} sentences)", ctx.search_terms, terms)),
+        ("Data File", ctx.data_file.clone()),
+        ("Parse Responses", ctx.parse_responses.to_string()),
+        ("Phase Timing", ctx.phase_timing.to_string()),
+        ("New Connection Per Request", ctx.new_connection_per_request.to_string()),
+    ];
+
+    config
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect()
+}
+
+/// Applies the index settings and schema before the documents are uploaded.
+///
+/// Returns if the benchmark created the lnx index and should delete it
+/// once the benchmark has finished. A resumed lnx upload keeps the index
+/// it created before being interrupted.
+async fn create_index(ctx: &Context, documents: &mut Documents, resumed: bool) -> Result<bool> {
+    if ctx.index_schema.is_some() && ctx.infer_schema {
+        return Err(anyhow!("an index schema can't be given when inferring the schema"));
+    }
+
+    let (address, index) = (ctx.address.as_str(), ctx.index.as_str());
+    match (ctx.target, ctx.index_settings.as_ref()) {
+        (BenchTarget::Lnx, settings) => {
+            let schema = match ctx.index_schema.as_ref() {
+                Some(schema) => Some(IndexSchema::from_value(schema)?),
+                None if ctx.infer_schema => Some(IndexSchema::infer(documents.sample().await?)?),
+                None => None,
+            };
+
+            if settings.is_none() && schema.is_none() {
+                return Ok(false);
+            }
+
+            if resumed {
+                info!("resuming the upload into the existing lnx index {:?}", index);
+                return Ok(true);
+            }
+
+            lnx::create_index(address, index, settings, schema.as_ref()).await?;
+            return Ok(true);
+        },
+        (BenchTarget::TypeSense, settings) => {
+            let fields = match ctx.index_schema.as_ref() {
+                Some(schema) => {
+                    let schema = IndexSchema::from_value(schema)?;
+                    Some(typesense::schema_fields(&schema, documents.sample().await?)?)
+                },
+                None if ctx.infer_schema => Some(typesense::infer_fields(documents.sample().await?)?),
+                None => None,
+            };
+
+            // The index settings are the collection schema, the fields are
+            // added to them when they come from an index schema.
+            let schema = match (settings, fields) {
+                (Some(settings), Some(_)) if settings.get("fields").is_some() => {
+                    return Err(anyhow!(
+                        "the collection fields can't be given in the index settings along with an index schema or inferred schema"
+                    ))
+                },
+                (settings, Some(fields)) => {
+                    let mut schema = match settings {
+                        Some(Value::Object(settings)) => settings.clone(),
+                        Some(_) => {
+                            return Err(anyhow!("the TypeSense collection schema must be an object"))
+                        },
+                        None => Map::new(),
+                    };
+                    schema.insert("fields".to_string(), Value::from(fields));
+                    Value::Object(schema)
+                },
+                (Some(settings), None) => settings.clone(),
+                (None, None) => return Ok(false),
+            };
+
+            typesense::create_collection(address, index, &schema).await?;
+            return Ok(true);
+        },
+        _ if ctx.index_schema.is_some() => {
+            return Err(anyhow!("index schemas are only supported by the lnx and TypeSense targets"))
+        },
+        _ if ctx.infer_schema => {
+            return Err(anyhow!("the schema can only be inferred for the lnx and TypeSense targets"))
+        },
+        (BenchTarget::MeiliSearch, Some(settings)) => {
+            meilisearch::update_settings(address, index, settings, ctx.api_key.as_deref()).await?
+        },
+        (BenchTarget::Generic, Some(_)) => {
+            return Err(anyhow!(
+                "index settings aren't supported by the generic target, use the create prep step instead"
+            ))
+        },
+        _ => {},
+    }
+
+    Ok(false)
+}
+
+/// Uploads the documents and returns the number of documents in the
+/// dataset.
+async fn upload_documents(
+    ctx: &Context,
+    mut documents: Documents,
+    upload: lnx::UploadOptions,
+    generic_config: Option<&generic::GenericConfig>,
+) -> Result<usize> {
+    let (address, index) = (ctx.address.as_str(), ctx.index.as_str());
+    match ctx.target {
+        BenchTarget::MeiliSearch => {
+            meilisearch::prep(address, &mut documents, index, ctx.api_key.as_deref()).await?
+        },
+        BenchTarget::Lnx => lnx::prep(address, &mut documents, index, upload).await?,
+        BenchTarget::TypeSense => typesense::prep(address, &mut documents, index).await?,
+        BenchTarget::Generic => {
+            let config =
+                generic_config.ok_or_else(|| anyhow!("missing generic target config"))?;
+            generic::prep(address, &mut documents, index, config).await?
+        },
+    }
+
+    Ok(documents.read())
+}
+
+/// Checks the index holds the same number of documents as the dataset.
+///
+/// The count is retried for a while as some targets take time to make new
+/// documents visible after they have been accepted.
+async fn verify_count(
+    ctx: &Context,
+    expected: usize,
+    generic_config: Option<&generic::GenericConfig>,
+) -> Result<()> {
+    let (address, index) = (ctx.address.as_str(), ctx.index.as_str());
+    let start = Instant::now();
+    loop {
+        let count = match ctx.target {
+            BenchTarget::MeiliSearch => {
+                meilisearch::count(address, index, ctx.api_key.as_deref()).await?
+            },
+            BenchTarget::Lnx => lnx::count(address, index).await?,
+            BenchTarget::TypeSense => typesense::count(address, index).await?,
+            BenchTarget::Generic => {
+                let config =
+                    generic_config.ok_or_else(|| anyhow!("missing generic target config"))?;
+                match generic::count(address, index, config).await? {
+                    Some(count) => count,
+                    None => {
+                        info!(
+                            "the generic target has no count step, the document count isn't verified"
+                        );
+                        return Ok(());
+                    },
+                }
+            },
+        };
+
+        if count == expected {
+            info!("verified the index {:?} holds all {} documents", index, count);
+            return Ok(());
+        }
+
+        if start.elapsed() >= VERIFY_COUNT_TIMEOUT {
+            let diff = if count < expected {
+                format!("{} documents are missing", expected - count)
+            } else {
+                format!("{} documents weren't in the dataset", count - expected)
+            };
+
+            return Err(anyhow!(
+                "the index {:?} holds {} documents but the data file {:?} has {}, {}. Pass --no-verify to benchmark it anyway",
+                index,
+                count,
+                ctx.data_file,
+                expected,
+                diff,
+            ));
+        }
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
     }
 }
 
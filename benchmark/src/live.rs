@@ -0,0 +1,192 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{IsTerminal, Write};
+use std::mem;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use crate::histogram::LatencyHistogram;
+
+/// How often the live view is redrawn.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How many refreshes the rolling percentiles cover.
+const ROLLING_WINDOW: usize = 5;
+
+/// How many refreshes pass between each progress line when stdout isn't
+/// a terminal.
+const LOG_EVERY: u64 = 10;
+
+/// The upper bounds in seconds of the latency buckets exported as metrics.
+pub(crate) const LATENCY_BUCKETS: [f64; 13] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// The stats shared between the workers, the live progress display and
+/// the metrics endpoint.
+#[derive(Default)]
+pub(crate) struct LiveStats {
+    pub(crate) requests: AtomicU64,
+    pub(crate) active_workers: AtomicUsize,
+    errors: Mutex<BTreeMap<u16, u64>>,
+
+    /// The latencies recorded since the display last refreshed.
+    interval: Mutex<LatencyHistogram>,
+
+    /// The number of requests completed for each response status.
+    pub(crate) statuses: Mutex<BTreeMap<u16, u64>>,
+
+    /// The number of successful requests within each of the `LATENCY_BUCKETS`,
+    /// each request is only counted in the smallest bucket it fits in.
+    pub(crate) latency_buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    pub(crate) latency_sum_micros: AtomicU64,
+    pub(crate) latency_count: AtomicU64,
+}
+
+impl LiveStats {
+    pub(crate) fn record_latency(&self, dur: Duration) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.interval.lock().unwrap().record(dur);
+
+        let seconds = dur.as_secs_f64();
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.latency_buckets[bucket].fetch_add(1, Ordering::Relaxed);
+        }
+        self.latency_sum_micros
+            .fetch_add(dur.as_micros() as u64, Ordering::Relaxed);
+        self.latency_count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_status(&self, status: u16) {
+        *self.statuses.lock().unwrap().entry(status).or_default() += 1;
+    }
+
+    pub(crate) fn record_error(&self, status: u16) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        *self.errors.lock().unwrap().entry(status).or_default() += 1;
+    }
+
+    /// Marks a worker as active until the returned guard is dropped.
+    pub(crate) fn worker_started(self: &Arc<Self>) -> WorkerGuard {
+        self.active_workers.fetch_add(1, Ordering::Relaxed);
+        WorkerGuard(self.clone())
+    }
+
+    fn take_interval(&self) -> LatencyHistogram {
+        mem::take(&mut *self.interval.lock().unwrap())
+    }
+}
+
+/// Counts a worker as active for as long as it is alive.
+pub(crate) struct WorkerGuard(Arc<LiveStats>);
+
+impl Drop for WorkerGuard {
+    fn drop(&mut self) {
+        self.0.active_workers.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// The background task displaying the progress of the run.
+pub(crate) struct Progress {
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl Progress {
+    pub(crate) fn start(stats: Arc<LiveStats>) -> Self {
+        let (stop, rx) = oneshot::channel();
+        let task = tokio::spawn(display(stats, rx));
+
+        Self { stop, task }
+    }
+
+    pub(crate) async fn stop(self) {
+        let _ = self.stop.send(());
+        let _ = self.task.await;
+    }
+}
+
+async fn display(stats: Arc<LiveStats>, mut stop: oneshot::Receiver<()>) {
+    let is_terminal = std::io::stdout().is_terminal();
+    let started = Instant::now();
+    let mut ticker = time::interval(REFRESH_INTERVAL);
+    let mut window: VecDeque<LatencyHistogram> = VecDeque::with_capacity(ROLLING_WINDOW);
+    let mut last_requests = 0;
+    let mut last_tick = Instant::now();
+    let mut drawn_lines = 0;
+    let mut ticks = 0u64;
+
+    // The first tick completes immediately.
+    ticker.tick().await;
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {},
+            _ = &mut stop => break,
+        }
+        ticks += 1;
+
+        if window.len() == ROLLING_WINDOW {
+            window.pop_front();
+        }
+        window.push_back(stats.take_interval());
+
+        let mut rolling = LatencyHistogram::new();
+        for hist in window.iter() {
+            let _ = rolling.add(hist);
+        }
+
+        let requests = stats.requests.load(Ordering::Relaxed);
+        let requests_second = (requests - last_requests) as f64 / last_tick.elapsed().as_secs_f64();
+        last_requests = requests;
+        last_tick = Instant::now();
+
+        let errors = stats
+            .errors
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(status, count)| format!("{}={}", status, count))
+            .collect::<Vec<String>>();
+        let errors = if errors.is_empty() {
+            "none".to_string()
+        } else {
+            errors.join(" ")
+        };
+
+        let elapsed = Duration::from_secs(started.elapsed().as_secs());
+        let active_workers = stats.active_workers.load(Ordering::Relaxed);
+        let p50 = rolling.percentile(50.0);
+        let p99 = rolling.percentile(99.0);
+
+        if is_terminal {
+            let lines = [
+                format!("     Elapsed: {:?}", elapsed),
+                format!("     Active Workers: {}", active_workers),
+                format!("     Requests/sec: {:.2} ({} total)", requests_second, requests),
+                format!("     Latency (last {}s): p50={:?} p99={:?}", ROLLING_WINDOW, p50, p99),
+                format!("     Errors: {}", errors),
+            ];
+
+            let mut stdout = std::io::stdout().lock();
+            if drawn_lines > 0 {
+                let _ = write!(stdout, "\x1b[{}A", drawn_lines);
+            }
+            for line in lines.iter() {
+                let _ = writeln!(stdout, "\x1b[2K{}", line);
+            }
+            let _ = stdout.flush();
+
+            drawn_lines = lines.len();
+        } else if ticks.is_multiple_of(LOG_EVERY) {
+            info!(
+                "Progress: elapsed={:?} workers={} req/s={:.2} p50={:?} p99={:?} errors={}",
+                elapsed, active_workers, requests_second, p50, p99, errors,
+            );
+        }
+    }
+}
@@ -1,26 +1,294 @@
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use anyhow::anyhow;
 use reqwest::StatusCode;
 
 use serde_json::Value;
 
+use tokio::task::JoinSet;
+
+use crate::checkpoint::Checkpoint;
+use crate::dataset::Documents;
 use crate::sampler::SamplerHandle;
-use crate::shared::{Query, RequestClient, TargetUri};
+use crate::schema::IndexSchema;
+use crate::shared::{
+    expect_array,
+    expect_duration,
+    ClientOptions,
+    ParsedResponse,
+    Query,
+    RequestClient,
+    SearchResult,
+    TargetUri,
+};
+
+/// The number of times a batch is sent before the upload fails.
+const UPLOAD_ATTEMPTS: u32 = 3;
+
+/// The number of uploaded batches between each commit and checkpoint.
+const CHECKPOINT_INTERVAL: usize = 10;
 
-pub(crate) async fn prep(address: &str, data: Value, index: &str) -> anyhow::Result<()> {
+/// How the documents are uploaded to lnx.
+pub(crate) struct UploadOptions {
+    /// The number of batches uploaded at once.
+    pub(crate) concurrency: usize,
+
+    /// The saved progress of the upload, if this is resumed the existing
+    /// documents are kept and the completed batches are skipped.
+    pub(crate) checkpoint: Option<Checkpoint>,
+}
+
+pub(crate) async fn prep(
+    address: &str,
+    documents: &mut Documents,
+    index: &str,
+    options: UploadOptions,
+) -> anyhow::Result<()> {
     let client = reqwest::Client::new();
+    let mut checkpoint = options.checkpoint;
+    let resumed = checkpoint.as_ref().map(Checkpoint::is_resumed).unwrap_or_default();
+
+    if resumed {
+        // A killed run can leave uploaded batches which were never committed
+        // or recorded, these would be indexed twice once the resumed run
+        // commits.
+        info!("resuming the upload from the checkpoint, the existing documents are kept");
+        rollback(&client, address, index).await?;
+    } else {
+        // Clear the existing docs
+        let _ = client
+            .delete(format!("{}/indexes/{}/documents/clear", address, index))
+            .send()
+            .await?;
+    }
+
+    let start = Instant::now();
+    let uploaded = upload_all(
+        &client,
+        address,
+        index,
+        documents,
+        checkpoint.as_mut(),
+        options.concurrency,
+    )
+    .await;
+
+    let skipped = match uploaded {
+        Ok(skipped) => skipped,
+        Err(e) => {
+            // The index is rolled back to the last commit which the saved
+            // checkpoint matches, so the batches uploaded since are sent
+            // again by the next run rather than being indexed twice.
+            if checkpoint.as_ref().map(Checkpoint::is_saved).unwrap_or_default() {
+                match rollback(&client, address, index).await {
+                    Ok(()) => {
+                        return Err(anyhow!("{}, the upload can be resumed from the checkpoint", e))
+                    },
+                    Err(rollback_error) => warn!(
+                        "failed to roll back the uncommitted documents, the checkpoint is no longer valid: {}",
+                        rollback_error
+                    ),
+                }
+            }
+
+            return Err(e);
+        },
+    };
+
+    commit(&client, address, index).await?;
+    if let Some(checkpoint) = checkpoint.as_ref() {
+        checkpoint.remove()?;
+    }
+
+    let delta = start.elapsed();
+    info!(
+        "lnx took {:?} to process {} submitted documents",
+        delta,
+        documents.read() - skipped,
+    );
+    if skipped > 0 {
+        info!("     skipped {} documents uploaded by the previous run", skipped);
+    }
+
+    Ok(())
+}
+
+/// Uploads every batch which isn't already completed with at most
+/// `concurrency` batches in flight, returning the number of documents
+/// skipped.
+///
+/// The checkpoint is saved every few batches once they have been committed.
+/// If the upload fails the batches still in flight are waited for rather
+/// than aborted so none of them can reach lnx after the upload has stopped.
+async fn upload_all(
+    client: &reqwest::Client,
+    address: &str,
+    index: &str,
+    documents: &mut Documents,
+    checkpoint: Option<&mut Checkpoint>,
+    concurrency: usize,
+) -> anyhow::Result<usize> {
+    let mut uploads = JoinSet::new();
+    let result = upload_batches(
+        client,
+        address,
+        index,
+        documents,
+        checkpoint,
+        concurrency,
+        &mut uploads,
+    )
+    .await;
+
+    while uploads.join_next().await.is_some() {}
+
+    result
+}
+
+async fn upload_batches(
+    client: &reqwest::Client,
+    address: &str,
+    index: &str,
+    documents: &mut Documents,
+    mut checkpoint: Option<&mut Checkpoint>,
+    concurrency: usize,
+    uploads: &mut JoinSet<anyhow::Result<usize>>,
+) -> anyhow::Result<usize> {
+    let url = Arc::new(format!("{}/indexes/{}/documents", address, index));
+    let mut reading = true;
+    let mut batch_id = 0;
+    let mut skipped = 0;
+    let mut since_checkpoint = 0;
+
+    loop {
+        // No more batches are started once enough have been sent for the
+        // next checkpoint, otherwise a batch could reach lnx before the
+        // commit without being recorded as completed.
+        let checkpoint_due = checkpoint.is_some() && since_checkpoint >= CHECKPOINT_INTERVAL;
+
+        if reading && !checkpoint_due && uploads.len() < concurrency.max(1) {
+            match documents.next_batch().await? {
+                Some(batch) => {
+                    let id = batch_id;
+                    batch_id += 1;
+
+                    if checkpoint.as_ref().map(|c| c.is_completed(id)).unwrap_or_default() {
+                        skipped += batch.len();
+                    } else {
+                        uploads.spawn(upload_batch(client.clone(), url.clone(), id, batch));
+                        since_checkpoint += 1;
+                    }
+
+                    continue;
+                },
+                None => reading = false,
+            }
+        }
 
-    // Clear the existing docs
-    let _ = client
-        .delete(format!("{}/indexes/{}/documents/clear", address, index))
+        let id = match uploads.join_next().await {
+            Some(result) => result??,
+            None if reading => {
+                // Every batch sent since the last checkpoint has been
+                // uploaded, so the checkpoint matches what is committed.
+                commit(client, address, index).await?;
+                if let Some(checkpoint) = checkpoint.as_ref() {
+                    checkpoint.save()?;
+                }
+
+                since_checkpoint = 0;
+                continue;
+            },
+            None => break,
+        };
+
+        if let Some(checkpoint) = checkpoint.as_mut() {
+            checkpoint.complete(id);
+        }
+    }
+
+    Ok(skipped)
+}
+
+/// Uploads the batch of documents, retrying if the request fails or lnx
+/// can't handle it right now.
+///
+/// lnx doesn't deduplicate documents, so a batch which lnx applied before
+/// the request failed or timed out is applied again by the retry.
+async fn upload_batch(
+    client: reqwest::Client,
+    url: Arc<String>,
+    id: usize,
+    batch: Vec<Value>,
+) -> anyhow::Result<usize> {
+    let mut attempt = 1;
+    loop {
+        let error = match client.post(url.as_str()).json(&batch).send().await {
+            Ok(r) if r.status() == StatusCode::OK => return Ok(id),
+            Ok(r) => {
+                let status = r.status();
+                let error = match r.text().await {
+                    Ok(data) => anyhow!("got unexpected response code {} data: {}", status, data),
+                    Err(e) => anyhow!("got unexpected response code {} and the response couldn't be read: {}", status, e),
+                };
+                if !(status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS) {
+                    return Err(error);
+                }
+
+                error
+            },
+            Err(e) => e.into(),
+        };
+
+        if attempt >= UPLOAD_ATTEMPTS {
+            return Err(anyhow!("batch {} failed after {} attempts: {}", id, attempt, error));
+        }
+
+        warn!("retrying batch {} after attempt {} failed: {}", id, attempt, error);
+        tokio::time::sleep(Duration::from_millis(500 * 2u64.pow(attempt))).await;
+        attempt += 1;
+    }
+}
+
+async fn commit(client: &reqwest::Client, address: &str, index: &str) -> anyhow::Result<()> {
+    let r = client
+        .post(format!("{}/indexes/{}/commit", address, index))
         .send()
         .await?;
 
-    let start = Instant::now();
+    if r.status() != StatusCode::OK {
+        return Err(anyhow!("got unexpected response code {} data: {}", r.status(), r.text().await?))
+    }
+
+    Ok(())
+}
+
+/// Discards every change since the last commit.
+async fn rollback(client: &reqwest::Client, address: &str, index: &str) -> anyhow::Result<()> {
     let r = client
-        .post(format!("{}/indexes/{}/documents", address, index))
-        .json(&data)
+        .post(format!("{}/indexes/{}/rollback", address, index))
+        .send()
+        .await?;
+
+    if r.status() != StatusCode::OK {
+        return Err(anyhow!("got unexpected response code {} data: {}", r.status(), r.text().await?))
+    }
+
+    Ok(())
+}
+
+/// Gets the number of documents in the index by searching for every
+/// document.
+pub(crate) async fn count(address: &str, index: &str) -> anyhow::Result<usize> {
+    let query = serde_json::json!({
+        "query": {
+            "normal": { "ctx": "*" },
+        },
+        "limit": 1,
+    });
+
+    let r = reqwest::Client::new()
+        .post(format!("{}/indexes/{}/search", address, index))
+        .json(&query)
         .send()
         .await?;
 
@@ -28,8 +296,62 @@ pub(crate) async fn prep(address: &str, data: Value, index: &str) -> anyhow::Res
         return Err(anyhow!("got unexpected response code {} data: {}", r.status(), r.text().await?))
     }
 
+    let data: Value = r.json().await?;
+    data.pointer("/data/count")
+        .and_then(Value::as_u64)
+        .map(|count| count as usize)
+        .ok_or_else(|| anyhow!("missing \"/data/count\" number in the search response"))
+}
+
+/// How long to wait for a newly created index to start serving searches.
+const INDEX_READY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Creates the index, replacing any existing index with the same name.
+///
+/// The given settings are applied over the same defaults the demo uses and
+/// the schema's fields are applied over the settings. Once created this
+/// waits until the index is serving searches.
+pub(crate) async fn create_index(
+    address: &str,
+    index: &str,
+    settings: Option<&Value>,
+    schema: Option<&IndexSchema>,
+) -> anyhow::Result<()> {
+    let defaults = serde_json::json!({
+        "writer_buffer": 60_000_000,
+        "writer_threads": 4,
+        "reader_threads": 1,
+        "max_concurrency": 4,
+        "storage_type": "tempdir",
+    });
+    let mut definition = defaults.as_object().cloned().unwrap_or_default();
+
+    if let Some(settings) = settings {
+        let settings = settings
+            .as_object()
+            .ok_or_else(|| anyhow!("the lnx index settings must be an object"))?;
+        definition.extend(settings.clone());
+    }
+
+    if let Some(schema) = schema {
+        definition.insert("fields".to_string(), Value::Object(schema.fields.clone()));
+        definition.insert("search_fields".to_string(), Value::from(schema.search_fields.clone()));
+    }
+
+    if !definition.contains_key("fields") {
+        return Err(anyhow!("the lnx index can't be created without any fields, give an index schema"));
+    }
+    definition.insert("name".to_string(), Value::from(index));
+
+    let payload = serde_json::json!({
+        "override_if_exists": true,
+        "index": definition,
+    });
+
+    let client = reqwest::Client::new();
     let r = client
-        .post(format!("{}/indexes/{}/commit", address, index))
+        .post(format!("{}/indexes", address))
+        .json(&payload)
         .send()
         .await?;
 
@@ -37,8 +359,59 @@ pub(crate) async fn prep(address: &str, data: Value, index: &str) -> anyhow::Res
         return Err(anyhow!("got unexpected response code {} data: {}", r.status(), r.text().await?))
     }
 
-    let delta = start.elapsed();
-    info!("lnx took {:?} to process submitted documents", delta);
+    wait_until_ready(&client, address, index).await?;
+    info!("created lnx index {:?}", index);
+
+    Ok(())
+}
+
+/// Waits until the index accepts searches.
+async fn wait_until_ready(client: &reqwest::Client, address: &str, index: &str) -> anyhow::Result<()> {
+    let start = Instant::now();
+    let query = serde_json::json!({
+        "query": {
+            "fuzzy": { "ctx": "ready" },
+        },
+        "limit": 1,
+    });
+
+    loop {
+        let r = client
+            .post(format!("{}/indexes/{}/search", address, index))
+            .json(&query)
+            .send()
+            .await?;
+
+        if r.status() == StatusCode::OK {
+            return Ok(())
+        }
+
+        if start.elapsed() > INDEX_READY_TIMEOUT {
+            return Err(anyhow!(
+                "the lnx index {:?} wasn't ready after {:?}, last response code {} data: {}",
+                index,
+                INDEX_READY_TIMEOUT,
+                r.status(),
+                r.text().await?,
+            ))
+        }
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+    }
+}
+
+/// Deletes the index and all of its documents.
+pub(crate) async fn delete_index(address: &str, index: &str) -> anyhow::Result<()> {
+    let r = reqwest::Client::new()
+        .delete(format!("{}/indexes/{}", address, index))
+        .send()
+        .await?;
+
+    if r.status() != StatusCode::OK {
+        return Err(anyhow!("got unexpected response code {} data: {}", r.status(), r.text().await?))
+    }
+
+    info!("deleted lnx index {:?}", index);
 
     Ok(())
 }
@@ -48,12 +421,14 @@ pub(crate) async fn bench_standard(
     sample: SamplerHandle,
     terms: Vec<String>,
     index: String,
+    options: ClientOptions,
 ) -> anyhow::Result<()> {
     crate::shared::start_standard(
         address,
         sample,
         terms,
         &index,
+        options,
         move |client, uri, query| async { search(client, uri, query).await },
     )
     .await
@@ -64,29 +439,139 @@ pub(crate) async fn bench_typing(
     sample: SamplerHandle,
     terms: Vec<String>,
     index: String,
+    options: ClientOptions,
 ) -> anyhow::Result<()> {
     crate::shared::start_typing(
         address,
         sample,
         terms,
         &index,
+        options,
         move |client, uri, query| async { search(client, uri, query).await },
     )
     .await
 }
 
-async fn search(client: RequestClient, uri: TargetUri, query: Query) -> anyhow::Result<u16> {
+async fn search(client: RequestClient, uri: TargetUri, query: Query) -> anyhow::Result<SearchResult> {
     let val = serde_json::json!({
         "query": {
             "fuzzy": { "ctx": query },
         },
     });
 
-    let r = client
+    let request = client
         .post(uri.as_ref())
-        .json(&val)
-        .send()
-        .await?;
+        .json(&val);
+
+    client.send_search(request, &[200], parse).await
+}
+
+fn parse(data: &Value) -> Result<ParsedResponse, String> {
+    let hits = expect_array(data, "/data/hits")?;
+    if !hits.iter().all(|hit| hit.get("doc").map(Value::is_object).unwrap_or(false)) {
+        return Err("hit is missing the \"doc\" object".to_string());
+    }
+
+    // lnx reports the time taken in seconds.
+    let server_time = expect_duration(data, "/data/time_taken", 1.0)?;
+
+    Ok(ParsedResponse {
+        hits: hits.len(),
+        server_time: Some(server_time),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::convert::Infallible;
+    use std::sync::Mutex;
 
-    Ok(r.status().as_u16())
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response, Server};
+
+    use super::*;
+
+    /// Uploads of this batch and every later one are rejected.
+    const FAILING_BATCH: u64 = 25;
+
+    /// The batches a fake lnx index has been sent, identified by the id of
+    /// their only document.
+    #[derive(Default)]
+    struct FakeIndex {
+        committed: BTreeSet<usize>,
+        uncommitted: BTreeSet<usize>,
+    }
+
+    async fn handle(
+        req: Request<Body>,
+        index: Arc<Mutex<FakeIndex>>,
+    ) -> Result<Response<Body>, Infallible> {
+        let path = req.uri().path().to_string();
+        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+        let mut status = StatusCode::OK;
+
+        if path.ends_with("/documents") {
+            let docs: Vec<Value> = serde_json::from_slice(&body).unwrap();
+            let id = docs[0]["id"].as_u64().unwrap();
+
+            // Staggers the uploads so they overlap the checkpoints.
+            tokio::time::sleep(Duration::from_millis(5 * (id % 3))).await;
+            if id >= FAILING_BATCH {
+                status = StatusCode::BAD_REQUEST;
+            } else {
+                index.lock().unwrap().uncommitted.insert(id as usize);
+            }
+        } else if path.ends_with("/commit") {
+            let mut index = index.lock().unwrap();
+            let uncommitted = std::mem::take(&mut index.uncommitted);
+            index.committed.extend(uncommitted);
+        } else if path.ends_with("/rollback") {
+            index.lock().unwrap().uncommitted.clear();
+        } else if path.ends_with("/clear") {
+            *index.lock().unwrap() = FakeIndex::default();
+        }
+
+        Ok(Response::builder().status(status).body(Body::empty()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn checkpoints_the_committed_batches() {
+        let index = Arc::new(Mutex::new(FakeIndex::default()));
+        let state = index.clone();
+        let make_service = make_service_fn(move |_| {
+            let index = state.clone();
+            async move { Ok::<_, Infallible>(service_fn(move |req| handle(req, index.clone()))) }
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+        let address = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+
+        let dir = std::env::temp_dir();
+        let data = dir.join(format!("lnxcli-upload-{}.jsonl", std::process::id()));
+        let data = data.to_str().unwrap();
+        let path = dir.join(format!("lnxcli-upload-{}.checkpoint.json", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let docs: String = (0..40).map(|id| format!("{{\"id\": {}}}\n", id)).collect();
+        std::fs::write(data, docs).unwrap();
+
+        let mut documents = Documents::open(data, 1).unwrap();
+        let options = UploadOptions {
+            concurrency: 4,
+            checkpoint: Some(Checkpoint::load(path, data, "bench", 1).unwrap()),
+        };
+        assert!(prep(&address, &mut documents, "bench", options).await.is_err());
+
+        let saved: Value = serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+        let completed: BTreeSet<usize> = serde_json::from_value(saved["completed"].clone()).unwrap();
+
+        let index = index.lock().unwrap();
+        assert_eq!(completed, index.committed);
+        assert_eq!(completed, (0..20).collect());
+        assert!(index.uncommitted.is_empty());
+
+        std::fs::remove_file(data).unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
 }
@@ -1,17 +1,37 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 
+use anyhow::anyhow;
 use chrono::Utc;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use reqwest::{RequestBuilder, StatusCode};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use tokio::time::Duration;
+use tokio::time::{Duration, Instant};
 
+use crate::dataset::Documents;
 use crate::sampler::SamplerHandle;
-use crate::shared::{Query, RequestClient, TargetUri};
+use crate::shared::{
+    expect_array,
+    expect_duration,
+    ClientOptions,
+    ParsedResponse,
+    Query,
+    RequestClient,
+    SearchResult,
+    TargetUri,
+};
+
+/// How often the task status is checked while waiting.
+const TASK_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long each prep task has to finish, and how long MeiliSearch has to
+/// finish its other tasks once the prep tasks have succeeded.
+const TASK_TIMEOUT: Duration = Duration::from_secs(60);
 
 #[derive(Debug, Deserialize)]
 struct EnqueueResponseData {
-    #[serde(rename = "uid")]
+    #[serde(rename = "taskUid", alias = "uid")]
     update_id: usize,
 
     #[serde(flatten)]
@@ -29,57 +49,220 @@ struct CheckData {
     #[serde(rename = "finishedAt")]
     finished: Option<chrono::DateTime<Utc>>,
 
+    /// Why the task failed, this is only set for failed tasks.
+    error: Option<TaskError>,
+
     #[serde(flatten)]
     _other: HashMap<String, Value>,
 }
 
-pub(crate) async fn prep(address: &str, data: Value, index: &str) -> anyhow::Result<()> {
-    let client = reqwest::Client::new();
+#[derive(Debug, Deserialize)]
+struct TaskError {
+    message: String,
+    code: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TaskList {
+    results: Vec<Value>,
+}
+
+/// Builds the client used to prep the index, every request is authorised
+/// with the API key if one is given.
+fn prep_client(api_key: Option<&str>) -> anyhow::Result<reqwest::Client> {
+    let mut headers = HeaderMap::new();
+    if let Some(key) = api_key {
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(&format!("Bearer {}", key))?);
+    }
+
+    Ok(reqwest::Client::builder().default_headers(headers).build()?)
+}
+
+/// Sends the request which enqueues a task and returns the task's uid.
+async fn enqueue(request: RequestBuilder) -> anyhow::Result<usize> {
+    let r = request.send().await?;
+    if r.status() != StatusCode::ACCEPTED {
+        return Err(anyhow!("got unexpected response code {} data: {}", r.status(), r.text().await?))
+    }
+
+    let data: EnqueueResponseData = r.json().await?;
+    Ok(data.update_id)
+}
+
+pub(crate) async fn prep(
+    address: &str,
+    documents: &mut Documents,
+    index: &str,
+    api_key: Option<&str>,
+) -> anyhow::Result<()> {
+    let client = prep_client(api_key)?;
 
-    // Clear the existing docs
+    // Clear the existing docs, this task fails if the index doesn't
+    // exist yet which is fine as there is nothing to clear.
     let _ = client
         .delete(format!("{}/indexes/{}/documents", address, index))
         .send()
         .await?;
 
-    let data: EnqueueResponseData = client
-        .post(format!("{}/indexes/{}/documents", address, index))
-        .json(&data)
+    // Every batch is enqueued before waiting so MeiliSearch can process
+    // them together.
+    let start = Instant::now();
+    let mut tasks = vec![];
+    while let Some(batch) = documents.next_batch().await? {
+        let task = enqueue(
+            client
+                .post(format!("{}/indexes/{}/documents", address, index))
+                .json(&batch),
+        )
+        .await?;
+
+        tasks.push(task);
+    }
+
+    for task in tasks {
+        wait_for_task(&client, address, task).await?;
+    }
+
+    info!(
+        "MeiliSearch took {:?} to process {} submitted documents",
+        start.elapsed(),
+        documents.read(),
+    );
+
+    wait_until_idle(&client, address).await?;
+
+    Ok(())
+}
+
+/// Gets the number of documents in the index.
+pub(crate) async fn count(address: &str, index: &str, api_key: Option<&str>) -> anyhow::Result<usize> {
+    let r = prep_client(api_key)?
+        .get(format!("{}/indexes/{}/stats", address, index))
         .send()
-        .await?
-        .json()
         .await?;
 
-    let status = data.update_id;
-    let delta;
+    if r.status() != StatusCode::OK {
+        return Err(anyhow!("got unexpected response code {} data: {}", r.status(), r.text().await?))
+    }
+
+    let stats: Value = r.json().await?;
+    stats
+        .get("numberOfDocuments")
+        .and_then(Value::as_u64)
+        .map(|count| count as usize)
+        .ok_or_else(|| anyhow!("missing \"numberOfDocuments\" number in the index stats"))
+}
 
+/// Updates the index settings and waits for MeiliSearch to apply them.
+///
+/// This includes the searchable, filterable and sortable attributes and the
+/// ranking rules, e.g. `{"searchableAttributes": ["title", "overview"]}`.
+pub(crate) async fn update_settings(
+    address: &str,
+    index: &str,
+    settings: &Value,
+    api_key: Option<&str>,
+) -> anyhow::Result<()> {
+    let client = prep_client(api_key)?;
+
+    let task = enqueue(
+        client
+            .patch(format!("{}/indexes/{}/settings", address, index))
+            .json(settings),
+    )
+    .await?;
+
+    let delta = wait_for_task(&client, address, task).await?;
+    info!(
+        "MeiliSearch took {:.2}s to apply the index settings",
+        delta.num_milliseconds() as f64 / 1000.0,
+    );
+
+    Ok(())
+}
+
+/// Waits for the task to succeed and returns how long it took to process.
+async fn wait_for_task(
+    client: &reqwest::Client,
+    address: &str,
+    task: usize,
+) -> anyhow::Result<chrono::Duration> {
+    let start = Instant::now();
     loop {
-        let data: CheckData = client
-            .get(format!("{}/tasks/{}", address, status))
+        let r = client
+            .get(format!("{}/tasks/{}", address, task))
             .send()
-            .await?
-            .json()
             .await?;
 
-        if data.status == "succeeded" {
-            delta = data.finished.unwrap() - data.started.unwrap();
-            break;
+        if r.status() != StatusCode::OK {
+            return Err(anyhow!("got unexpected response code {} data: {}", r.status(), r.text().await?))
+        }
+
+        let data: CheckData = r.json().await?;
+        match data.status.as_str() {
+            "succeeded" => {
+                return match (data.started, data.finished) {
+                    (Some(started), Some(finished)) => Ok(finished - started),
+                    _ => Ok(chrono::Duration::zero()),
+                }
+            },
+            "failed" => {
+                let (code, message) = data
+                    .error
+                    .map(|e| (e.code, e.message))
+                    .unwrap_or_else(|| ("unknown".to_string(), "no error given".to_string()));
+
+                return Err(anyhow!("MeiliSearch task {} failed ({}): {}", task, code, message))
+            },
+            "canceled" => return Err(anyhow!("MeiliSearch task {} was canceled", task)),
+            status => {
+                if start.elapsed() > TASK_TIMEOUT {
+                    return Err(anyhow!(
+                        "MeiliSearch task {} was still {} after {:?}",
+                        task,
+                        status,
+                        TASK_TIMEOUT,
+                    ));
+                }
+            },
         }
 
-        tokio::time::sleep(Duration::from_secs(1)).await;
+        tokio::time::sleep(TASK_POLL_INTERVAL).await;
     }
+}
 
-    info!(
-        "MeiliSearch took {}.{}s to process submitted documents",
-        delta.num_seconds(),
-        delta.num_milliseconds() / 100i64
-    );
+/// Waits until MeiliSearch has no enqueued or processing tasks left.
+async fn wait_until_idle(client: &reqwest::Client, address: &str) -> anyhow::Result<()> {
+    let start = Instant::now();
+    loop {
+        let r = client
+            .get(format!("{}/tasks?statuses=enqueued,processing&limit=1", address))
+            .send()
+            .await?;
 
-    info!("waiting 30 secs");
-    tokio::time::sleep(Duration::from_secs(30)).await;
+        if r.status() != StatusCode::OK {
+            return Err(anyhow!("got unexpected response code {} data: {}", r.status(), r.text().await?))
+        }
 
+        let tasks: TaskList = r.json().await?;
+        let pending = match tasks.results.first() {
+            Some(task) => task,
+            None => {
+                info!("MeiliSearch was idle after {:?}", start.elapsed());
+                return Ok(());
+            },
+        };
 
-    Ok(())
+        if start.elapsed() > TASK_TIMEOUT {
+            return Err(anyhow!(
+                "MeiliSearch still had enqueued or processing tasks after {:?}, e.g. {}",
+                TASK_TIMEOUT,
+                pending,
+            ));
+        }
+
+        tokio::time::sleep(TASK_POLL_INTERVAL).await;
+    }
 }
 
 pub(crate) async fn bench_standard(
@@ -87,13 +270,16 @@ pub(crate) async fn bench_standard(
     sample: SamplerHandle,
     terms: Vec<String>,
     index: String,
+    options: ClientOptions,
+    api_key: Option<Arc<String>>,
 ) -> anyhow::Result<()> {
     crate::shared::start_standard(
         address,
         sample,
         terms,
         &index,
-        move |client, uri, query| async { search(client, uri, query).await },
+        options,
+        move |client, uri, query| search(client, uri, api_key.clone(), query),
     )
     .await
 }
@@ -103,13 +289,16 @@ pub(crate) async fn bench_typing(
     sample: SamplerHandle,
     terms: Vec<String>,
     index: String,
+    options: ClientOptions,
+    api_key: Option<Arc<String>>,
 ) -> anyhow::Result<()> {
     crate::shared::start_typing(
         address,
         sample,
         terms,
         &index,
-        move |client, uri, query| async { search(client, uri, query).await },
+        options,
+        move |client, uri, query| search(client, uri, api_key.clone(), query),
     )
     .await
 }
@@ -119,12 +308,33 @@ struct QueryPayload {
     q: String,
 }
 
-async fn search(client: RequestClient, uri: TargetUri, query: Query) -> anyhow::Result<u16> {
-    let r = client
+async fn search(
+    client: RequestClient,
+    uri: TargetUri,
+    api_key: Option<Arc<String>>,
+    query: Query,
+) -> anyhow::Result<SearchResult> {
+    let mut request = client
         .post(uri.as_ref())
-        .json(&QueryPayload { q: query })
-        .send()
-        .await?;
+        .json(&QueryPayload { q: query });
+
+    if let Some(key) = api_key {
+        request = request.bearer_auth(key);
+    }
+
+    client.send_search(request, &[200], parse).await
+}
+
+fn parse(data: &Value) -> Result<ParsedResponse, String> {
+    let hits = expect_array(data, "/hits")?;
+    if !hits.iter().all(Value::is_object) {
+        return Err("hit is not an object".to_string());
+    }
+
+    let server_time = expect_duration(data, "/processingTimeMs", 0.001)?;
 
-    Ok(r.status().as_u16())
+    Ok(ParsedResponse {
+        hits: hits.len(),
+        server_time: Some(server_time),
+    })
 }
@@ -0,0 +1,120 @@
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use tokio::task::JoinHandle;
+
+use crate::live::{LiveStats, LATENCY_BUCKETS};
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Serves the live stats of the running benchmark in the Prometheus text
+/// format on `/metrics`.
+///
+/// The server runs until the returned task is aborted.
+pub(crate) fn serve(bind: SocketAddr, stats: Arc<LiveStats>) -> anyhow::Result<JoinHandle<()>> {
+    let server = Server::try_bind(&bind)?;
+
+    let make_service = make_service_fn(move |_| {
+        let stats = stats.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let stats = stats.clone();
+                async move { Ok::<_, Infallible>(handle(req, &stats)) }
+            }))
+        }
+    });
+
+    info!("serving benchmark metrics on http://{}/metrics", bind);
+    let task = tokio::spawn(async move {
+        if let Err(e) = server.serve(make_service).await {
+            error!("metrics server failed: {}", e);
+        }
+    });
+
+    Ok(task)
+}
+
+fn handle(req: Request<Body>, stats: &LiveStats) -> Response<Body> {
+    if req.method() != Method::GET || req.uri().path() != "/metrics" {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    Response::builder()
+        .header("Content-Type", CONTENT_TYPE)
+        .body(Body::from(render(stats)))
+        .unwrap()
+}
+
+/// Renders the stats in the Prometheus text exposition format.
+fn render(stats: &LiveStats) -> String {
+    let mut out = String::new();
+
+    let _ = writeln!(out, "# HELP lnxcli_bench_requests_total Search requests completed by response status.");
+    let _ = writeln!(out, "# TYPE lnxcli_bench_requests_total counter");
+    for (status, count) in stats.statuses.lock().unwrap().iter() {
+        let _ = writeln!(out, "lnxcli_bench_requests_total{{status=\"{}\"}} {}", status, count);
+    }
+
+    let _ = writeln!(out, "# HELP lnxcli_bench_request_duration_seconds Latency of successful search requests.");
+    let _ = writeln!(out, "# TYPE lnxcli_bench_request_duration_seconds histogram");
+    let mut cumulative = 0;
+    for (bound, count) in LATENCY_BUCKETS.iter().zip(stats.latency_buckets.iter()) {
+        cumulative += count.load(Ordering::Relaxed);
+        let _ = writeln!(
+            out,
+            "lnxcli_bench_request_duration_seconds_bucket{{le=\"{}\"}} {}",
+            bound, cumulative
+        );
+    }
+    let count = stats.latency_count.load(Ordering::Relaxed);
+    let sum = stats.latency_sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+    let _ = writeln!(out, "lnxcli_bench_request_duration_seconds_bucket{{le=\"+Inf\"}} {}", count);
+    let _ = writeln!(out, "lnxcli_bench_request_duration_seconds_sum {}", sum);
+    let _ = writeln!(out, "lnxcli_bench_request_duration_seconds_count {}", count);
+
+    let _ = writeln!(out, "# HELP lnxcli_bench_active_workers Workers currently sending searches.");
+    let _ = writeln!(out, "# TYPE lnxcli_bench_active_workers gauge");
+    let _ = writeln!(
+        out,
+        "lnxcli_bench_active_workers {}",
+        stats.active_workers.load(Ordering::Relaxed)
+    );
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn cumulative_buckets() {
+        let stats = LiveStats::default();
+        stats.record_latency(Duration::from_micros(500));
+        stats.record_latency(Duration::from_millis(20));
+        stats.record_latency(Duration::from_secs(30));
+        stats.record_status(200);
+        stats.record_status(200);
+        stats.record_status(503);
+
+        let text = render(&stats);
+
+        assert!(text.contains("lnxcli_bench_requests_total{status=\"200\"} 2\n"));
+        assert!(text.contains("lnxcli_bench_requests_total{status=\"503\"} 1\n"));
+        assert!(text.contains("lnxcli_bench_request_duration_seconds_bucket{le=\"0.001\"} 1\n"));
+        assert!(text.contains("lnxcli_bench_request_duration_seconds_bucket{le=\"0.025\"} 2\n"));
+        assert!(text.contains("lnxcli_bench_request_duration_seconds_bucket{le=\"10\"} 2\n"));
+        assert!(text.contains("lnxcli_bench_request_duration_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(text.contains("lnxcli_bench_request_duration_seconds_count 3\n"));
+    }
+}
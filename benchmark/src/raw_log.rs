@@ -0,0 +1,193 @@
+use std::time::Duration;
+
+use anyhow::anyhow;
+use chrono::{DateTime, SecondsFormat, Utc};
+use tokio::fs::File;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+const HEADER: &str = "timestamp,worker,query,prefix_length,status,latency_us,bytes\n";
+
+/// A single request written to the raw log.
+pub(crate) struct RawRecord {
+    /// When the request was sent.
+    pub(crate) timestamp: DateTime<Utc>,
+    pub(crate) worker: usize,
+    pub(crate) query: String,
+
+    /// The number of characters typed, this is `None` outside of the
+    /// typing mode.
+    pub(crate) prefix_length: Option<usize>,
+    pub(crate) status: u16,
+    pub(crate) latency: Duration,
+
+    /// The size of the response body.
+    pub(crate) bytes: usize,
+}
+
+impl RawRecord {
+    fn to_csv(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{}\n",
+            self.timestamp.to_rfc3339_opts(SecondsFormat::Micros, true),
+            self.worker,
+            escape(&self.query),
+            self.prefix_length.map(|v| v.to_string()).unwrap_or_default(),
+            self.status,
+            self.latency.as_micros(),
+            self.bytes,
+        )
+    }
+
+    fn from_record(record: &csv::StringRecord) -> anyhow::Result<Self> {
+        if record.len() != 7 {
+            return Err(anyhow!("expected 7 fields but got {}", record.len()));
+        }
+
+        let prefix_length = if record[3].is_empty() {
+            None
+        } else {
+            Some(record[3].parse()?)
+        };
+
+        Ok(Self {
+            timestamp: DateTime::parse_from_rfc3339(&record[0])?.with_timezone(&Utc),
+            worker: record[1].parse()?,
+            query: record[2].to_string(),
+            prefix_length,
+            status: record[4].parse()?,
+            latency: Duration::from_micros(record[5].parse()?),
+            bytes: record[6].parse()?,
+        })
+    }
+}
+
+/// Streams the records of a raw log written by a previous run, the log is
+/// read as the records are consumed so any length of log can be read.
+pub(crate) fn read(path: &str) -> anyhow::Result<impl Iterator<Item = anyhow::Result<RawRecord>>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_path(path)
+        .map_err(|e| anyhow!("failed to open the raw request log {:?}: {}", path, e))?;
+
+    let header: Vec<&str> = HEADER.trim_end().split(',').collect();
+    match reader.headers() {
+        Ok(fields) if fields.iter().eq(header.iter().copied()) => {},
+        _ => return Err(anyhow!("{} is not a raw request log, the header is missing", path)),
+    }
+
+    let path = path.to_string();
+    Ok(reader.into_records().map(move |record| {
+        let record = record?;
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        RawRecord::from_record(&record)
+            .map_err(|e| anyhow!("invalid record on line {} of {}: {}", line, path, e))
+    }))
+}
+
+/// Quotes the field if it contains a character with a special meaning in CSV.
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Streams a CSV record of every request to disk.
+///
+/// The workers only push records onto a channel, the file is written by a
+/// background task so disk IO doesn't affect the measured latencies.
+pub(crate) struct RawLog {
+    path: String,
+    sender: mpsc::UnboundedSender<RawRecord>,
+    writer: JoinHandle<anyhow::Result<()>>,
+}
+
+impl RawLog {
+    pub(crate) async fn create(path: &str) -> anyhow::Result<Self> {
+        let mut file = BufWriter::new(File::create(path).await?);
+        file.write_all(HEADER.as_bytes()).await?;
+
+        let (sender, mut receiver) = mpsc::unbounded_channel::<RawRecord>();
+        let writer = tokio::spawn(async move {
+            while let Some(record) = receiver.recv().await {
+                file.write_all(record.to_csv().as_bytes()).await?;
+            }
+
+            file.flush().await?;
+            Ok(())
+        });
+
+        Ok(Self {
+            path: path.to_string(),
+            sender,
+            writer,
+        })
+    }
+
+    pub(crate) fn sender(&self) -> mpsc::UnboundedSender<RawRecord> {
+        self.sender.clone()
+    }
+
+    /// Waits for all of the sent records to be written.
+    ///
+    /// Every sender handed out must be dropped before this completes.
+    pub(crate) async fn finish(self) -> anyhow::Result<()> {
+        drop(self.sender);
+        self.writer.await??;
+
+        info!("Raw request log has been saved to {}", self.path);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_round_trip() {
+        let record = RawRecord {
+            timestamp: Utc::now(),
+            worker: 3,
+            query: "say \"hello\", world".to_string(),
+            prefix_length: Some(4),
+            status: 200,
+            latency: Duration::from_micros(1500),
+            bytes: 512,
+        };
+
+        let path = std::env::temp_dir().join(format!("lnxcli-raw-log-{}.csv", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, format!("{}{}", HEADER, record.to_csv())).unwrap();
+
+        let records: Vec<RawRecord> = read(path).unwrap().map(Result::unwrap).collect();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(records.len(), 1);
+
+        let parsed = &records[0];
+        assert_eq!(parsed.query, record.query);
+        assert_eq!(parsed.prefix_length, Some(4));
+        assert_eq!(parsed.latency, record.latency);
+        assert_eq!(parsed.timestamp.timestamp_micros(), record.timestamp.timestamp_micros());
+    }
+
+    #[test]
+    fn empty_prefix_length() {
+        let record = csv::StringRecord::from(vec![
+            "2021-09-01T12:00:00.000000Z",
+            "0",
+            "cars",
+            "",
+            "404",
+            "120",
+            "0",
+        ]);
+        let parsed = RawRecord::from_record(&record).unwrap();
+
+        assert_eq!(parsed.prefix_length, None);
+        assert_eq!(parsed.status, 404);
+    }
+}
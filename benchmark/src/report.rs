@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use tokio::fs;
+
+use crate::html;
+use crate::raw_log;
+use crate::summary::RunSummary;
+use crate::ReportContext;
+
+/// Regenerates the stats and charts of a previous run.
+pub(crate) async fn generate(ctx: ReportContext) -> anyhow::Result<()> {
+    let is_raw_log = ctx.input.ends_with(".csv");
+    if !is_raw_log && (ctx.since.is_some() || ctx.until.is_some()) {
+        return Err(anyhow!(
+            "a time window can only be applied to a raw request log, the run data only has the merged histograms"
+        ));
+    }
+
+    let summary = if is_raw_log {
+        let (path, since, until) = (ctx.input.clone(), ctx.since, ctx.until);
+        tokio::task::spawn_blocking(move || summarise(&path, since, until)).await??
+    } else {
+        RunSummary::load(&ctx.input).await?
+    };
+
+    if summary.latencies.is_empty() {
+        return Err(anyhow!("there are no successful requests to report on"));
+    }
+
+    fs::create_dir_all(&ctx.output).await?;
+    summary.render(&ctx.output, &ctx.chart_options)?;
+    html::write(&ctx.output, &summary, &[], &ctx.chart_options).await
+}
+
+/// Builds the run summary from the raw request records sent within the
+/// window, the window is relative to when the first request was sent.
+///
+/// The log is streamed twice rather than held in memory, first to find when
+/// the first request was sent as the records are written in the order the
+/// requests complete, then to summarise the records in the window.
+///
+/// The raw log doesn't record the target's success statuses so any 2xx
+/// status is treated as a successful search.
+fn summarise(path: &str, since: Option<Duration>, until: Option<Duration>) -> anyhow::Result<RunSummary> {
+    let mut summary = RunSummary::default();
+    let mut worker_totals: HashMap<usize, (usize, Duration)> = HashMap::new();
+
+    let mut start = None;
+    let mut total = 0;
+    for record in raw_log::read(path)? {
+        let timestamp = record?.timestamp;
+        start = Some(start.map_or(timestamp, |start: DateTime<Utc>| start.min(timestamp)));
+        total += 1;
+    }
+    info!("loaded {} requests from {}", total, path);
+
+    let start = match start {
+        Some(start) => start,
+        None => return Ok(summary),
+    };
+
+    for record in raw_log::read(path)? {
+        let record = record?;
+        let offset = (record.timestamp - start).to_std().unwrap_or_default();
+        if since.map(|v| offset < v).unwrap_or(false) || until.map(|v| offset > v).unwrap_or(false) {
+            continue;
+        }
+
+        summary.add_status_latency(record.status, record.latency);
+        summary.add_query(&record.query, record.status, record.latency);
+        if !(200..300).contains(&record.status) {
+            *summary.errors.entry(record.status).or_default() += 1;
+            continue;
+        }
+
+        summary.latencies.record(record.latency);
+        if let Some(length) = record.prefix_length {
+            summary.add_latency_for_sentence_length(length, record.latency);
+        }
+
+        let (count, elapsed) = worker_totals.entry(record.worker).or_default();
+        *count += 1;
+        *elapsed += record.latency;
+    }
+
+    summary.requests_second = worker_totals
+        .values()
+        .map(|(count, elapsed)| *count as f64 / elapsed.as_secs_f64())
+        .sum();
+
+    Ok(summary)
+}
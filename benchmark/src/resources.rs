@@ -0,0 +1,332 @@
+use std::fs;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use serde::Serialize;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use crate::charts::{self, ChartOptions};
+use crate::live::LiveStats;
+
+/// How often the server process is sampled.
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The server process resources and the benchmark throughput at one point
+/// in the run.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ResourceSample {
+    /// The seconds since sampling started.
+    pub(crate) elapsed: f64,
+    pub(crate) requests_second: f64,
+
+    /// The mean latency of the successful requests completed since the
+    /// last sample in milliseconds.
+    pub(crate) mean_latency_ms: f64,
+    pub(crate) cpu_percent: f64,
+    pub(crate) rss_bytes: u64,
+    pub(crate) threads: u64,
+    pub(crate) open_fds: u64,
+
+    /// The disk IO rates, these are `None` if `/proc/<pid>/io` isn't
+    /// readable which is normally the case for another user's process.
+    pub(crate) disk_read_bytes_second: Option<f64>,
+    pub(crate) disk_write_bytes_second: Option<f64>,
+}
+
+/// The raw counters read from `/proc/<pid>`.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+struct ProcessStats {
+    /// The user and system CPU time in clock ticks.
+    cpu_ticks: u64,
+    rss_bytes: u64,
+    threads: u64,
+    open_fds: u64,
+
+    /// The bytes read from and written to disk.
+    io: Option<(u64, u64)>,
+}
+
+/// Gets the pid of the process with the given name.
+pub(crate) fn find_process(name: &str) -> anyhow::Result<u32> {
+    let own_pid = std::process::id();
+    let mut found = vec![];
+
+    for entry in fs::read_dir("/proc")? {
+        let entry = entry?;
+        let pid: u32 = match entry.file_name().to_str().and_then(|v| v.parse().ok()) {
+            Some(pid) => pid,
+            None => continue,
+        };
+
+        if pid == own_pid {
+            continue;
+        }
+
+        // The comm name is truncated to 15 characters so the first
+        // argument of the command line is checked as well.
+        let comm = fs::read_to_string(entry.path().join("comm")).unwrap_or_default();
+        let cmdline = fs::read(entry.path().join("cmdline")).unwrap_or_default();
+        let program = cmdline.split(|b| *b == 0).next().unwrap_or_default();
+        let program = String::from_utf8_lossy(program);
+        let program = program.rsplit('/').next().unwrap_or_default();
+
+        if comm.trim_end() == name || program == name {
+            found.push(pid);
+        }
+    }
+
+    match found.as_slice() {
+        [pid] => Ok(*pid),
+        [] => Err(anyhow!("no process named {:?} is running", name)),
+        pids => Err(anyhow!(
+            "multiple processes named {:?} are running ({:?}), pass the pid instead",
+            name,
+            pids
+        )),
+    }
+}
+
+fn read_process(pid: u32, page_size: u64) -> anyhow::Result<ProcessStats> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid))?;
+    let (cpu_ticks, threads, rss_pages) =
+        parse_stat(&stat).ok_or_else(|| anyhow!("unable to parse /proc/{}/stat", pid))?;
+
+    let open_fds = fs::read_dir(format!("/proc/{}/fd", pid))?.count() as u64;
+    let io = fs::read_to_string(format!("/proc/{}/io", pid))
+        .ok()
+        .and_then(|io| parse_io(&io));
+
+    Ok(ProcessStats {
+        cpu_ticks,
+        rss_bytes: rss_pages * page_size,
+        threads,
+        open_fds,
+        io,
+    })
+}
+
+/// Gets the CPU ticks, thread count and resident pages from the contents
+/// of `/proc/<pid>/stat`.
+fn parse_stat(stat: &str) -> Option<(u64, u64, u64)> {
+    // The process name is wrapped in brackets and may contain spaces so the
+    // fields are counted from the closing bracket, which is after field 2.
+    let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+    let field = |n: usize| fields.get(n - 3)?.parse::<u64>().ok();
+
+    let utime = field(14)?;
+    let stime = field(15)?;
+    let threads = field(20)?;
+    let rss_pages = field(24)?;
+
+    Some((utime + stime, threads, rss_pages))
+}
+
+/// Gets the bytes read and written from the contents of `/proc/<pid>/io`.
+fn parse_io(io: &str) -> Option<(u64, u64)> {
+    let value = |key: &str| {
+        io.lines()
+            .find_map(|line| line.strip_prefix(key))
+            .and_then(|v| v.trim().parse::<u64>().ok())
+    };
+
+    Some((value("read_bytes:")?, value("write_bytes:")?))
+}
+
+/// Samples the server process resources in the background.
+pub(crate) struct ResourceMonitor {
+    stop: oneshot::Sender<()>,
+    task: JoinHandle<Vec<ResourceSample>>,
+}
+
+impl ResourceMonitor {
+    pub(crate) fn start(pid: u32, stats: Arc<LiveStats>) -> anyhow::Result<Self> {
+        // SAFETY: `sysconf` only reads the system configuration and takes no
+        // pointers, an unsupported name returns -1 which is checked below.
+        let (clock_ticks, page_size) = unsafe {
+            (
+                libc::sysconf(libc::_SC_CLK_TCK),
+                libc::sysconf(libc::_SC_PAGESIZE),
+            )
+        };
+        if clock_ticks <= 0 || page_size <= 0 {
+            return Err(anyhow!(
+                "unable to get the clock tick rate and page size, got {} and {}",
+                clock_ticks,
+                page_size
+            ));
+        }
+
+        let initial = read_process(pid, page_size as u64)?;
+        let (stop, rx) = oneshot::channel();
+        let task = tokio::spawn(sample(
+            pid,
+            stats,
+            initial,
+            clock_ticks as f64,
+            page_size as u64,
+            rx,
+        ));
+
+        info!("sampling the resources of server process {}", pid);
+        Ok(Self { stop, task })
+    }
+
+    /// Stops sampling and returns the samples taken.
+    pub(crate) async fn stop(self) -> Vec<ResourceSample> {
+        let _ = self.stop.send(());
+        self.task.await.unwrap_or_default()
+    }
+}
+
+async fn sample(
+    pid: u32,
+    stats: Arc<LiveStats>,
+    initial: ProcessStats,
+    clock_ticks: f64,
+    page_size: u64,
+    mut stop: oneshot::Receiver<()>,
+) -> Vec<ResourceSample> {
+    let started = Instant::now();
+    let mut ticker = time::interval(SAMPLE_INTERVAL);
+    let mut samples = vec![];
+
+    let mut last = initial;
+    let mut last_tick = Instant::now();
+    let mut last_requests = stats.requests.load(Ordering::Relaxed);
+    let mut last_latency_sum = stats.latency_sum_micros.load(Ordering::Relaxed);
+    let mut last_latency_count = stats.latency_count.load(Ordering::Relaxed);
+
+    // The first tick completes immediately.
+    ticker.tick().await;
+
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {},
+            _ = &mut stop => break,
+        }
+
+        // The reads of `/proc` block, so they are kept off the runtime
+        // threads the benchmark workers are measured on.
+        let current = tokio::task::spawn_blocking(move || read_process(pid, page_size))
+            .await
+            .unwrap_or_else(|e| Err(e.into()));
+
+        let current = match current {
+            Ok(current) => current,
+            Err(e) => {
+                warn!("stopped sampling server process {}: {}", pid, e);
+                break;
+            },
+        };
+
+        let interval = last_tick.elapsed().as_secs_f64();
+        last_tick = Instant::now();
+
+        let requests = stats.requests.load(Ordering::Relaxed);
+        let latency_sum = stats.latency_sum_micros.load(Ordering::Relaxed);
+        let latency_count = stats.latency_count.load(Ordering::Relaxed);
+        let completed = latency_count - last_latency_count;
+        let mean_latency_ms = if completed == 0 {
+            0.0
+        } else {
+            (latency_sum - last_latency_sum) as f64 / completed as f64 / 1000.0
+        };
+
+        let cpu_seconds = current.cpu_ticks.saturating_sub(last.cpu_ticks) as f64 / clock_ticks;
+        let io_rates = match (current.io, last.io) {
+            (Some((read, write)), Some((last_read, last_write))) => Some((
+                read.saturating_sub(last_read) as f64 / interval,
+                write.saturating_sub(last_write) as f64 / interval,
+            )),
+            _ => None,
+        };
+
+        samples.push(ResourceSample {
+            elapsed: started.elapsed().as_secs_f64(),
+            requests_second: (requests - last_requests) as f64 / interval,
+            mean_latency_ms,
+            cpu_percent: cpu_seconds / interval * 100.0,
+            rss_bytes: current.rss_bytes,
+            threads: current.threads,
+            open_fds: current.open_fds,
+            disk_read_bytes_second: io_rates.map(|(read, _)| read),
+            disk_write_bytes_second: io_rates.map(|(_, write)| write),
+        });
+
+        last = current;
+        last_requests = requests;
+        last_latency_sum = latency_sum;
+        last_latency_count = latency_count;
+    }
+
+    samples
+}
+
+/// Logs the resource usage of the server and saves the samples and the
+/// timeline chart to the output directory.
+pub(crate) async fn report(output: &str, samples: &[ResourceSample]) -> anyhow::Result<()> {
+    if samples.is_empty() {
+        return Ok(());
+    }
+
+    log_summary(samples);
+
+    let chart = format!("{}/resources.png", output);
+    charts::save_png(&chart, &ChartOptions::default(), |root| {
+        charts::resource_timeline(root, samples)
+    })?;
+    info!("Resource timeline has been saved to {}", chart);
+
+    let data = format!("{}/resources.json", output);
+    tokio::fs::write(&data, serde_json::to_vec_pretty(samples)?).await?;
+    info!("Resource samples have been saved to {}", data);
+
+    Ok(())
+}
+
+/// Logs the peak and average resource usage of the server.
+fn log_summary(samples: &[ResourceSample]) {
+    let count = samples.len() as f64;
+    let max = |f: &dyn Fn(&ResourceSample) -> f64| samples.iter().map(f).fold(0.0, f64::max);
+    let mean = |f: &dyn Fn(&ResourceSample) -> f64| samples.iter().map(f).sum::<f64>() / count;
+
+    info!("Server resource results:");
+    info!("     Average CPU: {:.1}%", mean(&|s| s.cpu_percent));
+    info!("     Peak CPU: {:.1}%", max(&|s| s.cpu_percent));
+    info!("     Peak Memory: {:.1} MB", max(&|s| s.rss_bytes as f64) / 1_000_000.0);
+    info!("     Peak Threads: {}", max(&|s| s.threads as f64));
+    info!("     Peak Open Files: {}", max(&|s| s.open_fds as f64));
+
+    if samples.iter().any(|s| s.disk_read_bytes_second.is_some()) {
+        let total = |f: &dyn Fn(&ResourceSample) -> Option<f64>| {
+            samples.iter().filter_map(f).sum::<f64>() * SAMPLE_INTERVAL.as_secs_f64()
+        };
+
+        info!("     Disk Read: {:.1} MB", total(&|s| s.disk_read_bytes_second) / 1_000_000.0);
+        info!("     Disk Written: {:.1} MB", total(&|s| s.disk_write_bytes_second) / 1_000_000.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_stat_with_spaces_in_name() {
+        let stat = "1234 (lnx (worker) 1) S 1 1234 1234 0 -1 4194560 2253 0 0 0 \
+                    150 75 0 0 20 0 12 0 8841 1056768000 4096 18446744073709551615";
+
+        assert_eq!(parse_stat(stat), Some((225, 12, 4096)));
+    }
+
+    #[test]
+    fn parse_io_counters() {
+        let io = "rchar: 100\nwchar: 200\nsyscr: 3\nsyscw: 4\nread_bytes: 4096\nwrite_bytes: 8192\n";
+
+        assert_eq!(parse_io(io), Some((4096, 8192)));
+    }
+}
@@ -1,44 +1,73 @@
-use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
 use anyhow::anyhow;
-use hdrhistogram::Histogram as HdrHistogram;
-use tokio::sync::oneshot;
+use chrono::{DateTime, Utc};
+use tokio::sync::{mpsc, oneshot};
 use tokio::time::Duration;
-use plotters::prelude::*;
+
+use crate::charts::ChartOptions;
+use crate::live::{LiveStats, WorkerGuard};
+use crate::raw_log::{RawLog, RawRecord};
+use crate::shared::{ParsedResponse, SearchResult};
+use crate::summary::RunSummary;
+use crate::timing::PhaseTimings;
 
 pub(crate) type ChannelMessage = SampleData;
 
 /// The data sampled from the benchmark
 pub(crate) struct SampleData {
-    /// All request latencies.
-    latencies: Vec<Duration>,
+    /// The results recorded by this worker.
+    summary: RunSummary,
 
-    /// All request latencies.
-    sentence_length_latencies: Vec<Vec<Duration>>,
+    /// The total time spent on successful requests.
+    total_elapsed: Duration,
 
-    errors: HashMap<u16, usize>,
-
-    requests_second: f64,
+    /// Every successful request latency in the order they were sent, this
+    /// is only kept if raw samples are enabled.
+    raw_latencies: Option<Vec<Duration>>,
 }
 
 pub(crate) struct SamplerHandle {
     sample: SampleData,
     submit: oneshot::Sender<ChannelMessage>,
+
+    /// The id of the worker this handle belongs to.
+    worker: usize,
+
+    /// Sends each request to the raw log if it is enabled.
+    raw_log: Option<mpsc::UnboundedSender<RawRecord>>,
+
+    /// The stats shown by the live progress display.
+    live: Arc<LiveStats>,
+
+    /// Counts the worker as active until the handle is finished.
+    _active: WorkerGuard,
+
+    /// When the worker should stop searching if the run has a fixed duration.
+    deadline: Option<Instant>,
 }
 
 impl SamplerHandle {
     pub(crate) fn finish(mut self) {
-        let total_elapsed = self.sample.latencies.iter().sum::<Duration>();
-        self.sample.requests_second = self.sample.latencies.len() as f64 / total_elapsed.as_secs_f64();
+        let total_elapsed = self.sample.total_elapsed;
+        self.sample.summary.requests_second =
+            self.sample.summary.latencies.len() as f64 / total_elapsed.as_secs_f64();
 
         let _ = self.submit.send(self.sample);
     }
 
-    pub(crate) fn new() -> (Self, oneshot::Receiver<ChannelMessage>) {
+    pub(crate) fn new(
+        worker: usize,
+        keep_raw_samples: bool,
+        raw_log: Option<mpsc::UnboundedSender<RawRecord>>,
+        live: Arc<LiveStats>,
+        deadline: Option<Instant>,
+    ) -> (Self, oneshot::Receiver<ChannelMessage>) {
         let sample = SampleData {
-            latencies: vec![],
-            sentence_length_latencies: vec![],
-            errors: HashMap::new(),
-            requests_second: 0.0
+            summary: RunSummary::default(),
+            total_elapsed: Duration::ZERO,
+            raw_latencies: if keep_raw_samples { Some(vec![]) } else { None },
         };
 
         let (tx, rx) = oneshot::channel();
@@ -46,172 +75,213 @@ impl SamplerHandle {
         let inst = Self {
             sample,
             submit: tx,
+            worker,
+            raw_log,
+            _active: live.worker_started(),
+            live,
+            deadline,
         };
 
         (inst, rx)
     }
 
+    /// If the run has a fixed duration rather than a single pass of the terms.
+    pub(crate) fn has_deadline(&self) -> bool {
+        self.deadline.is_some()
+    }
+
+    /// If the run has a fixed duration which has passed.
+    pub(crate) fn expired(&self) -> bool {
+        self.deadline.map(|v| Instant::now() >= v).unwrap_or(false)
+    }
+
     pub(crate) fn add_latency(&mut self, dur: Duration) {
-        self.sample.latencies.push(dur);
+        self.sample.summary.latencies.record(dur);
+        self.sample.total_elapsed += dur;
+        self.live.record_latency(dur);
+
+        if let Some(raw) = self.sample.raw_latencies.as_mut() {
+            raw.push(dur);
+        }
     }
 
     pub(crate) fn add_latency_for_sentence_length(&mut self, length: usize, dur: Duration) {
-        if length >= self.sample.sentence_length_latencies.len() {
-            for i in self.sample.sentence_length_latencies.len()..length + 1 {
-                self.sample.sentence_length_latencies.insert(i, vec![]);
-            }
+        self.sample.summary.add_latency_for_sentence_length(length, dur);
+    }
+
+    pub(crate) fn add_status_latency(&mut self, status: u16, dur: Duration) {
+        self.sample.summary.add_status_latency(status, dur);
+        self.live.record_status(status);
+    }
+
+    pub(crate) fn add_query(&mut self, query: &str, status: u16, dur: Duration) {
+        self.sample.summary.add_query(query, status, dur);
+    }
+
+    pub(crate) fn add_phase_timings(&mut self, timings: PhaseTimings) {
+        let phases = &mut self.sample.summary.phases;
+
+        if let Some(dns) = timings.dns {
+            phases.dns.record(dns);
+        }
+        if let Some(connect) = timings.connect {
+            phases.connect.record(connect);
+        }
+        if let Some(tls) = timings.tls {
+            phases.tls.record(tls);
+        }
+        phases.ttfb.record(timings.ttfb);
+        phases.body.record(timings.body);
+    }
+
+    pub(crate) fn register_response(
+        &mut self,
+        parsed: Result<ParsedResponse, String>,
+        latency: Duration,
+    ) {
+        let summary = &mut self.sample.summary;
+        match parsed {
+            Ok(response) => {
+                if let Some(server_time) = response.server_time {
+                    summary.server_latencies.record(server_time);
+                    summary
+                        .overhead_latencies
+                        .record(latency.saturating_sub(server_time));
+                }
+
+                summary.valid_responses += 1;
+                summary.total_hits += response.hits as u64;
+                if response.hits == 0 {
+                    summary.zero_results += 1;
+                }
+            },
+            Err(reason) => {
+                *summary.invalid_responses.entry(reason).or_default() += 1;
+            },
         }
+    }
 
-        self.sample.sentence_length_latencies[length].push(dur);
+    /// Sends the request to the raw log if it is enabled.
+    pub(crate) fn log_request(
+        &self,
+        timestamp: DateTime<Utc>,
+        query: &str,
+        prefix_length: Option<usize>,
+        result: &SearchResult,
+        latency: Duration,
+    ) {
+        if let Some(raw_log) = self.raw_log.as_ref() {
+            let _ = raw_log.send(RawRecord {
+                timestamp,
+                worker: self.worker,
+                query: query.to_string(),
+                prefix_length,
+                status: result.status,
+                latency,
+                bytes: result.bytes,
+            });
+        }
     }
 
     pub(crate) fn register_error(&mut self, status: u16) {
-        let exists = self.sample.errors.get(&status);
-        let v = if let Some(v) = exists { *v + 1 } else { 1 };
-        self.sample.errors.insert(status, v);
+        *self.sample.summary.errors.entry(status).or_default() += 1;
+        self.live.record_error(status);
     }
 }
 
 pub(crate) struct Sampler {
     output: String,
+    keep_raw_samples: bool,
+    raw_log: Option<RawLog>,
+    live: Arc<LiveStats>,
+    deadline: Option<Instant>,
     sample_handles: Vec<oneshot::Receiver<ChannelMessage>>,
 }
 
 impl Sampler {
-    pub(crate) fn new(output: String) -> Self {
+    pub(crate) fn new(output: String, keep_raw_samples: bool, raw_log: Option<RawLog>) -> Self {
         Self {
             output,
+            keep_raw_samples,
+            raw_log,
+            live: Arc::new(LiveStats::default()),
+            deadline: None,
             sample_handles: vec![],
         }
     }
 
+    /// The stats updated by the workers as the run progresses.
+    pub(crate) fn live_stats(&self) -> Arc<LiveStats> {
+        self.live.clone()
+    }
+
+    /// Stops the workers at the given time rather than after a single pass
+    /// of the terms, this must be set before the handles are created.
+    pub(crate) fn set_deadline(&mut self, deadline: Instant) {
+        self.deadline = Some(deadline);
+    }
+
     pub(crate) fn get_handle(&mut self) -> SamplerHandle {
-        let (handler, rx) = SamplerHandle::new();
+        let (handler, rx) = SamplerHandle::new(
+            self.sample_handles.len(),
+            self.keep_raw_samples,
+            self.raw_log.as_ref().map(|log| log.sender()),
+            self.live.clone(),
+            self.deadline,
+        );
 
         self.sample_handles.push(rx);
 
         handler
     }
 
-    pub(crate) async fn wait_and_sample(self) -> anyhow::Result<()> {
-        let mut req_sec = vec![];
-        let mut all_results: Vec<Duration> = vec![];
-        let mut all_sentence_length_latencies: HashMap<usize, Vec<Duration>> = HashMap::new();
-        let mut errors = HashMap::new();
-        let output = format!("{}/run-output.png", self.output);
+    /// Merges the results of every worker, logs them and saves them to
+    /// the output directory along with the settings the run used.
+    pub(crate) async fn wait_and_sample(
+        self,
+        config: Vec<(String, String)>,
+    ) -> anyhow::Result<RunSummary> {
+        let mut summary = RunSummary {
+            config,
+            ..RunSummary::default()
+        };
+        let mut raw_latencies = vec![];
 
         for sample in self.sample_handles {
-            let mut res = match sample.await {
+            let res = match sample.await {
                 Ok(r) => r,
                 Err(_) => continue,
             };
 
-            req_sec.push(res.requests_second);
-            all_results.append(&mut res.latencies);
-
-            for (length, mut latencies) in res.sentence_length_latencies.drain(..).enumerate() {
-                let contains = {
-                    all_sentence_length_latencies.contains_key(&length)
-                };
-
-                if contains {
-                    all_sentence_length_latencies
-                        .get_mut(&length)
-                        .unwrap()
-                        .append(&mut latencies);
-                } else {
-                    all_sentence_length_latencies.insert(length, latencies);
-                }
-            }
-
-            for (status, count) in res.errors {
-                let v = errors.get(&status);
-                let v = if let Some(v) = v { *v + count } else { count };
-
-                errors.insert(status, v);
-            }
-        }
-
-        if all_results.is_empty() {
-            return Err(anyhow!("Unable to succesfully complete test due to no tasks succeeding"));
-        }
+            summary.add(&res.summary)?;
 
-        let mut hist = HdrHistogram::<u64>::new_with_bounds(1, 60 * 60 * 1000, 2).unwrap();
-
-        hist.auto(true);
-        for result in all_results.iter() {
-            if result.as_micros() > 0 {
-                hist.record(result.as_micros() as u64)?;
+            if let Some(mut raw) = res.raw_latencies {
+                raw_latencies.append(&mut raw);
             }
         }
 
-        // Calculate the total time spent handling successful requests by adding up all the time
-        // taken processing the requests then divide by the concurrency factor as that allows upto
-        // n requests to happen in parallel.
-        let requests_a_sec = req_sec.iter().sum::<f64>() as f64;
-
-        info!("General benchmark results:");
-        info!("     Total Succesful Requests Sent: {}", all_results.len());
-        info!("     Average Requests/sec: {:.2}", requests_a_sec);
-        info!("     Average Latency: {:?}", Duration::from_secs_f64(hist.mean() / (1000f64.powf(2.0))));
-        info!("     Max Latency: {:?}", Duration::from_micros(hist.max()));
-        info!("     Min Latency: {:?}", Duration::from_micros(hist.min()));
-        info!("     Stdev Latency: {:?}", Duration::from_secs_f64(hist.stdev() / (1000f64.powf(2.0))));
-
-        for (code, amount) in errors {
-            warn!("     Got status {}: {}", code, amount);
+        if let Some(raw_log) = self.raw_log {
+            raw_log.finish().await?;
         }
 
-        let mut data: Vec<u32> = vec![0; all_sentence_length_latencies.keys().copied().max().unwrap_or(0)];
-        for (length, durations) in all_sentence_length_latencies {
-            if length == 0 {
-                continue;
-            }
-
-            let avg: u64 = if durations.is_empty() {
-                0u64
-            } else {
-                durations.iter()
-                    .map(|v| v.as_millis() as u64)
-                    .sum::<u64>() / durations.len() as u64
-            };
-            data[length-1] = avg as u32;
+        if summary.latencies.is_empty() {
+            return Err(anyhow!("Unable to succesfully complete test due to no tasks succeeding"));
         }
-        let max_latency = data.iter().copied().max().unwrap_or(0u32);
-        let max_length = data.len() as u32;
-
-
-        let root = BitMapBackend::new(&output, (1920, 1080)).into_drawing_area();
 
-        root.fill(&WHITE)?;
+        summary.render(&self.output, &ChartOptions::default())?;
 
-        let mut chart = ChartBuilder::on(&root)
-            .x_label_area_size(75)
-            .y_label_area_size(75)
-            .margin(5)
-            .caption("Searching Latency Graph", ("sans-serif", 50.0))
-            .build_cartesian_2d((1u32..max_length).into_segmented(), 0u32..max_latency)?;
+        let run_data = format!("{}/run-data.json", self.output);
+        summary.save(&run_data).await?;
+        info!("Run data has been saved to {}", run_data);
 
-        chart
-            .configure_mesh()
-            .disable_x_mesh()
-            .bold_line_style(&WHITE.mix(0.5))
-            .y_desc("Avg Latency (ms)")
-            .x_desc("Sentence Length")
-            .label_style(("sans-serif", 32))
-            .axis_desc_style(("sans-serif", 48))
-            .draw()?;
+        if self.keep_raw_samples {
+            let micros: Vec<u64> = raw_latencies.iter().map(|v| v.as_micros() as u64).collect();
 
-        chart.draw_series(
-            Histogram::vertical(&chart)
-                .style(RED.mix(0.5).filled())
-                .data(data.iter().enumerate().map(|(y, x)| ((y+1) as u32, *x))),
-        )?;
-
-        // To avoid the IO failure being ignored silently, we manually call the present function
-        let _ = root.present();
-        info!("Result has been saved to {}", output);
+            let raw_output = format!("{}/raw-latencies.json", self.output);
+            tokio::fs::write(&raw_output, serde_json::to_vec(&micros)?).await?;
+            info!("Raw latency samples have been saved to {}", raw_output);
+        }
 
-        Ok(())
+        Ok(summary)
     }
-}
\ No newline at end of file
+}
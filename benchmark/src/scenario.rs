@@ -0,0 +1,441 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use serde::de::{self, Deserializer};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::summary::RunSummary;
+use crate::sweep;
+use crate::{BenchMode, BenchTarget, Context};
+
+/// The number of documents uploaded in each request if not set.
+const DEFAULT_BATCH_SIZE: usize = 10_000;
+
+/// The number of batches uploaded to lnx at once if not set.
+const DEFAULT_UPLOAD_CONCURRENCY: usize = 4;
+
+/// The benchmark settings which can be given on the command line or in a
+/// scenario file.
+///
+/// Each value is optional so the layers can be merged, a value set by a
+/// flag takes priority over the scenario which takes priority over the
+/// file's defaults.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BenchOptions {
+    pub address: Option<String>,
+    pub target: Option<BenchTarget>,
+    pub target_config: Option<String>,
+    pub mode: Option<BenchMode>,
+    pub data_file: Option<String>,
+    pub search_terms: Option<String>,
+    pub concurrency: Option<usize>,
+    pub threads: Option<usize>,
+
+    /// How many seconds to keep searching for.
+    pub duration: Option<u64>,
+    pub batch_size: Option<usize>,
+    pub upload_concurrency: Option<usize>,
+    pub upload_checkpoint: Option<String>,
+    pub output_dir: Option<String>,
+    pub index: Option<String>,
+    pub index_settings: Option<Value>,
+    pub index_schema: Option<Value>,
+    pub infer_schema: Option<bool>,
+    pub keep_index: Option<bool>,
+    pub query_by: Option<Vec<String>>,
+    pub api_key: Option<String>,
+    pub no_prep: Option<bool>,
+    pub no_verify: Option<bool>,
+    pub parse_responses: Option<bool>,
+    pub phase_timing: Option<bool>,
+    pub new_connection_per_request: Option<bool>,
+    pub keep_raw_samples: Option<bool>,
+    pub raw_log: Option<String>,
+    pub metrics_bind: Option<SocketAddr>,
+    pub server_pid: Option<u32>,
+    pub server_process: Option<String>,
+
+    /// The values to sweep over, a benchmark is run for every combination.
+    #[serde(default)]
+    pub sweep: SweepOptions,
+}
+
+/// The values to sweep the benchmark over, any value which isn't swept is
+/// taken from the benchmark options.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SweepOptions {
+    pub concurrency: Option<Vec<usize>>,
+    pub threads: Option<Vec<usize>>,
+    pub mode: Option<Vec<BenchMode>>,
+
+    /// The lnx index settings to sweep over and the values to try for each,
+    /// these are applied on top of the base index settings.
+    pub index_settings: Option<BTreeMap<String, Vec<Value>>>,
+}
+
+impl SweepOptions {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.concurrency.is_none()
+            && self.threads.is_none()
+            && self.mode.is_none()
+            && self.index_settings.is_none()
+    }
+
+    fn or(self, other: Self) -> Self {
+        Self {
+            concurrency: self.concurrency.or(other.concurrency),
+            threads: self.threads.or(other.threads),
+            mode: self.mode.or(other.mode),
+            index_settings: self.index_settings.or(other.index_settings),
+        }
+    }
+}
+
+impl BenchOptions {
+    /// Fills any unset values from the other options.
+    fn or(self, other: Self) -> Self {
+        Self {
+            address: self.address.or(other.address),
+            target: self.target.or(other.target),
+            target_config: self.target_config.or(other.target_config),
+            mode: self.mode.or(other.mode),
+            data_file: self.data_file.or(other.data_file),
+            search_terms: self.search_terms.or(other.search_terms),
+            concurrency: self.concurrency.or(other.concurrency),
+            threads: self.threads.or(other.threads),
+            duration: self.duration.or(other.duration),
+            batch_size: self.batch_size.or(other.batch_size),
+            upload_concurrency: self.upload_concurrency.or(other.upload_concurrency),
+            upload_checkpoint: self.upload_checkpoint.or(other.upload_checkpoint),
+            output_dir: self.output_dir.or(other.output_dir),
+            index: self.index.or(other.index),
+            index_settings: self.index_settings.or(other.index_settings),
+            index_schema: self.index_schema.or(other.index_schema),
+            infer_schema: self.infer_schema.or(other.infer_schema),
+            keep_index: self.keep_index.or(other.keep_index),
+            query_by: self.query_by.or(other.query_by),
+            api_key: self.api_key.or(other.api_key),
+            no_prep: self.no_prep.or(other.no_prep),
+            no_verify: self.no_verify.or(other.no_verify),
+            parse_responses: self.parse_responses.or(other.parse_responses),
+            phase_timing: self.phase_timing.or(other.phase_timing),
+            new_connection_per_request: self
+                .new_connection_per_request
+                .or(other.new_connection_per_request),
+            keep_raw_samples: self.keep_raw_samples.or(other.keep_raw_samples),
+            raw_log: self.raw_log.or(other.raw_log),
+            metrics_bind: self.metrics_bind.or(other.metrics_bind),
+            server_pid: self.server_pid.or(other.server_pid),
+            server_process: self.server_process.or(other.server_process),
+            sweep: self.sweep.or(other.sweep),
+        }
+    }
+
+    /// Builds the benchmark context, the swept values are used in place of
+    /// any missing values which are swept.
+    fn into_context(self, output: String) -> anyhow::Result<Context> {
+        let swept_concurrency = self.sweep.concurrency.as_ref().and_then(|v| v.first().copied());
+        let swept_mode = self.sweep.mode.as_ref().and_then(|v| v.first().copied());
+
+        Ok(Context {
+            address: required(self.address, "address")?,
+            data_file: required(self.data_file, "data_file")?,
+            concurrency: required(self.concurrency.or(swept_concurrency), "concurrency")?,
+            target: required(self.target, "target")?,
+            mode: required(self.mode.or(swept_mode), "mode")?,
+            threads: self.threads.unwrap_or_else(num_cpus::get),
+            output,
+            search_terms: required(self.search_terms, "search_terms")?,
+            no_prep: self.no_prep.unwrap_or_default(),
+            no_verify: self.no_verify.unwrap_or_default(),
+            index: self.index.unwrap_or_else(|| "bench".to_string()),
+            duration: self.duration.map(Duration::from_secs),
+            batch_size: self.batch_size.unwrap_or(DEFAULT_BATCH_SIZE),
+            upload_concurrency: self.upload_concurrency.unwrap_or(DEFAULT_UPLOAD_CONCURRENCY),
+            upload_checkpoint: self.upload_checkpoint,
+            index_settings: self.index_settings,
+            index_schema: self.index_schema,
+            infer_schema: self.infer_schema.unwrap_or_default(),
+            keep_index: self.keep_index.unwrap_or_default(),
+            query_by: self.query_by,
+            api_key: self.api_key,
+            target_config: self.target_config,
+            parse_responses: self.parse_responses.unwrap_or_default(),
+            phase_timing: self.phase_timing.unwrap_or_default(),
+            new_connection_per_request: self.new_connection_per_request.unwrap_or_default(),
+            keep_raw_samples: self.keep_raw_samples.unwrap_or_default(),
+            raw_log: self.raw_log,
+            metrics_bind: self.metrics_bind,
+            server_pid: self.server_pid,
+            server_process: self.server_process,
+        })
+    }
+}
+
+fn required<T>(value: Option<T>, name: &str) -> anyhow::Result<T> {
+    value.ok_or_else(|| {
+        anyhow!(
+            "the {:?} setting is required, pass --{} or set it in the scenario file",
+            name,
+            name.replace('_', "-"),
+        )
+    })
+}
+
+/// A file describing one or more benchmark scenarios to run in sequence.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScenarioFile {
+    /// The settings shared by every scenario.
+    #[serde(default)]
+    defaults: BenchOptions,
+
+    #[serde(rename = "scenario")]
+    scenarios: Vec<Scenario>,
+}
+
+#[derive(Debug)]
+struct Scenario {
+    /// The name of the scenario, the results are written to a directory
+    /// of the same name within the output directory.
+    name: String,
+
+    options: BenchOptions,
+}
+
+impl<'de> Deserialize<'de> for Scenario {
+    /// The options sit alongside the name, flattening them would accept
+    /// any unknown key so the name is taken out of the table and the rest
+    /// is deserialized as the options.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut table = toml::value::Table::deserialize(deserializer)?;
+        let name = match table.remove("name") {
+            Some(toml::Value::String(name)) => name,
+            Some(_) => return Err(de::Error::custom("the scenario name must be a string")),
+            None => return Err(de::Error::missing_field("name")),
+        };
+
+        let options = toml::Value::Table(table)
+            .try_into()
+            .map_err(|e| de::Error::custom(format!("in the scenario {:?}: {}", name, e)))?;
+
+        Ok(Self { name, options })
+    }
+}
+
+fn load(path: &str) -> anyhow::Result<ScenarioFile> {
+    let data = fs::read_to_string(path)?;
+    let file: ScenarioFile = toml::from_str(&data)?;
+
+    if file.scenarios.is_empty() {
+        return Err(anyhow!("the scenario file {:?} has no scenarios", path));
+    }
+
+    let mut names = HashSet::new();
+    for scenario in file.scenarios.iter() {
+        if scenario.name.is_empty() || scenario.name.contains(['/', '\\']) {
+            return Err(anyhow!(
+                "invalid scenario name {:?}, names must be non-empty and can't contain path separators",
+                scenario.name
+            ));
+        }
+
+        if !names.insert(scenario.name.as_str()) {
+            return Err(anyhow!("the scenario name {:?} is used more than once", scenario.name));
+        }
+    }
+
+    Ok(file)
+}
+
+/// The headline results of a scenario.
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct ScenarioResult {
+    pub(crate) name: String,
+    pub(crate) target: String,
+    pub(crate) mode: String,
+    pub(crate) concurrency: usize,
+    pub(crate) threads: usize,
+    pub(crate) requests: u64,
+    pub(crate) errors: usize,
+    pub(crate) requests_second: f64,
+    pub(crate) mean_latency_ms: f64,
+    pub(crate) p50_latency_ms: f64,
+    pub(crate) p90_latency_ms: f64,
+    pub(crate) p99_latency_ms: f64,
+    pub(crate) max_latency_ms: f64,
+}
+
+impl ScenarioResult {
+    pub(crate) fn new(name: String, ctx: &Context) -> Self {
+        Self {
+            name,
+            target: format!("{:?}", ctx.target),
+            mode: format!("{:?}", ctx.mode),
+            concurrency: ctx.concurrency,
+            threads: ctx.threads,
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn record(&mut self, summary: &RunSummary) {
+        let hist = &summary.latencies;
+        let ms = |p: f64| hist.percentile(p).as_secs_f64() * 1000.0;
+
+        self.requests = hist.len();
+        self.errors = summary.errors.values().sum();
+        self.requests_second = summary.requests_second;
+        self.mean_latency_ms = hist.mean() / 1000.0;
+        self.p50_latency_ms = ms(50.0);
+        self.p90_latency_ms = ms(90.0);
+        self.p99_latency_ms = ms(99.0);
+        self.max_latency_ms = hist.max() as f64 / 1000.0;
+    }
+}
+
+/// Runs every scenario in the file one after another, the options given
+/// override the values set by the file.
+///
+/// If no file is given a single benchmark is run from the options.
+pub fn run_scenarios(path: Option<&str>, overrides: BenchOptions) -> anyhow::Result<()> {
+    let path = match path {
+        Some(path) => path,
+        None => {
+            let output = required(overrides.output_dir.clone(), "output_dir")?;
+            let sweep = overrides.sweep.clone();
+            let ctx = overrides.into_context(output)?;
+
+            if sweep.is_empty() {
+                return crate::run(ctx);
+            }
+
+            sweep::run(ctx, &sweep)?;
+            return Ok(());
+        },
+    };
+
+    let file = load(path)?;
+    let root = overrides
+        .output_dir
+        .clone()
+        .or_else(|| file.defaults.output_dir.clone());
+
+    let total = file.scenarios.len();
+    let mut results = vec![];
+    for (i, scenario) in file.scenarios.into_iter().enumerate() {
+        info!("running scenario {:?} ({}/{})", scenario.name, i + 1, total);
+
+        let options = overrides
+            .clone()
+            .or(scenario.options)
+            .or(file.defaults.clone());
+        let output = required(options.output_dir.clone(), "output_dir")?;
+        let output = format!("{}/{}", output, scenario.name);
+        fs::create_dir_all(&output)?;
+
+        let sweep = options.sweep.clone();
+        let ctx = options.into_context(output)?;
+
+        if !sweep.is_empty() {
+            let name = scenario.name;
+            let cells = sweep::run(ctx, &sweep)
+                .map_err(|e| anyhow!("scenario {:?} failed: {}", name, e))?;
+            for mut cell in cells {
+                cell.name = format!("{}/{}", name, cell.name);
+                results.push(cell);
+            }
+            continue;
+        }
+
+        let mut result = ScenarioResult::new(scenario.name, &ctx);
+        let summary = crate::run_summarised(ctx)
+            .map_err(|e| anyhow!("scenario {:?} failed: {}", result.name, e))?;
+
+        result.record(&summary);
+        results.push(result);
+    }
+
+    log_results(&results);
+
+    if let Some(root) = root {
+        let path = format!("{}/scenarios.json", root);
+        fs::write(&path, serde_json::to_vec_pretty(&results)?)?;
+        info!("Scenario results have been saved to {}", path);
+    }
+
+    Ok(())
+}
+
+fn log_results(results: &[ScenarioResult]) {
+    info!("Scenario results:");
+    for result in results {
+        info!(
+            "     {}: {:.2} req/s, mean={:.2}ms p50={:.2}ms p99={:.2}ms, {} requests, {} errors",
+            result.name,
+            result.requests_second,
+            result.mean_latency_ms,
+            result.p50_latency_ms,
+            result.p99_latency_ms,
+            result.requests,
+            result.errors,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scenario_overrides_defaults() {
+        let file: ScenarioFile = toml::from_str(
+            r#"
+            [defaults]
+            target = "lnx"
+            address = "http://127.0.0.1:8000"
+            mode = "standard"
+            concurrency = 8
+
+            [[scenario]]
+            name = "typing"
+            mode = "typing"
+            index_settings = { use_fast_fuzzy = true }
+            "#,
+        )
+        .unwrap();
+
+        let cli = BenchOptions {
+            concurrency: Some(32),
+            ..BenchOptions::default()
+        };
+        let scenario = file.scenarios.into_iter().next().unwrap();
+        let options = cli.or(scenario.options).or(file.defaults);
+
+        assert!(matches!(options.mode, Some(BenchMode::Typing)));
+        assert!(matches!(options.target, Some(BenchTarget::Lnx)));
+        assert_eq!(options.concurrency, Some(32));
+        assert_eq!(options.address.as_deref(), Some("http://127.0.0.1:8000"));
+        assert_eq!(options.index_settings, Some(serde_json::json!({ "use_fast_fuzzy": true })));
+    }
+
+    #[test]
+    fn rejects_unknown_scenario_keys() {
+        let error = toml::from_str::<ScenarioFile>(
+            r#"
+            [[scenario]]
+            name = "standard"
+            concurency = 8
+            "#,
+        )
+        .unwrap_err();
+
+        assert!(error.to_string().contains("concurency"), "{}", error);
+        assert!(toml::from_str::<ScenarioFile>("[[scenario]]\nconcurrency = 8\n").is_err());
+    }
+}
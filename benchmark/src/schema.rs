@@ -0,0 +1,222 @@
+use std::collections::BTreeMap;
+
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+/// The number of documents looked at when inferring the schema.
+const INFER_SAMPLE_SIZE: usize = 1000;
+
+/// The fields of an lnx index and the fields searched by default.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct IndexSchema {
+    /// The field definitions keyed by the field name, e.g.
+    /// `{"title": {"type": "text", "stored": true}}`.
+    pub(crate) fields: Map<String, Value>,
+
+    /// The fields searched when a query doesn't name any, if empty every
+    /// text field is searched.
+    #[serde(default)]
+    pub(crate) search_fields: Vec<String>,
+}
+
+impl IndexSchema {
+    /// Reads the schema given in the benchmark settings.
+    pub(crate) fn from_value(value: &Value) -> anyhow::Result<Self> {
+        let mut schema: Self = serde_json::from_value(value.clone())
+            .map_err(|e| anyhow!("invalid index schema: {}", e))?;
+
+        if schema.fields.is_empty() {
+            return Err(anyhow!("the index schema must define at least one field"));
+        }
+
+        if schema.search_fields.is_empty() {
+            schema.search_fields = schema.text_fields();
+        }
+
+        if let Some(missing) = schema
+            .search_fields
+            .iter()
+            .find(|f| !schema.fields.contains_key(*f))
+        {
+            return Err(anyhow!(
+                "the search field {:?} isn't defined in the index schema",
+                missing
+            ));
+        }
+
+        Ok(schema)
+    }
+
+    /// Infers the schema from a sample of the documents being uploaded.
+    ///
+    /// Strings and arrays of strings become stored text fields except for
+    /// the `id` field and fields ending in `_id` which become stored string
+    /// fields, numbers become stored fast fields. Any other values can't be
+    /// mapped and are left out of the schema.
+    pub(crate) fn infer(docs: &[Value]) -> anyhow::Result<Self> {
+        let mut fields = Map::new();
+        for (name, field) in infer_fields(docs)? {
+            let is_id = name == "id" || name.ends_with("_id");
+            let field = match field.kind {
+                FieldKind::Text if is_id => json!({ "type": "string", "stored": true }),
+                FieldKind::Text => json!({ "type": "text", "stored": true }),
+                FieldKind::Integer => {
+                    json!({ "type": "i64", "stored": true, "indexed": false, "fast": true })
+                },
+                FieldKind::Float => {
+                    json!({ "type": "f64", "stored": true, "indexed": false, "fast": true })
+                },
+                FieldKind::Empty => continue,
+                FieldKind::Bool | FieldKind::Unsupported => {
+                    warn!(
+                        "the field {:?} can't be inferred and is left out of the schema",
+                        name
+                    );
+                    continue;
+                },
+            };
+
+            fields.insert(name, field);
+        }
+
+        if fields.is_empty() {
+            return Err(anyhow!("no fields could be inferred from the documents"));
+        }
+
+        let mut schema = Self {
+            fields,
+            search_fields: vec![],
+        };
+        schema.search_fields = schema.text_fields();
+
+        info!("inferred index schema: {}", serde_json::to_string(&schema)?);
+
+        Ok(schema)
+    }
+
+    fn text_fields(&self) -> Vec<String> {
+        self.fields
+            .iter()
+            .filter(|(_, field)| field.get("type").and_then(Value::as_str) == Some("text"))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
+
+/// Infers the type of every field from a sample of the documents.
+pub(crate) fn infer_fields(docs: &[Value]) -> anyhow::Result<BTreeMap<String, InferredField>> {
+    // The field kinds and if they hold arrays, this is `None` until a value
+    // other than null has been seen.
+    let mut seen: BTreeMap<&str, (FieldKind, Option<bool>)> = BTreeMap::new();
+    for doc in docs.iter().take(INFER_SAMPLE_SIZE) {
+        let doc = doc.as_object().ok_or_else(|| {
+            anyhow!("the schema can only be inferred from JSON object documents")
+        })?;
+
+        for (key, value) in doc.iter() {
+            let (kind, array) = seen.entry(key.as_str()).or_insert((FieldKind::Empty, None));
+            if value.is_null() {
+                continue;
+            }
+
+            *kind = kind.merge(FieldKind::of(value));
+            match array {
+                Some(is_array) if *is_array != value.is_array() => *kind = FieldKind::Unsupported,
+                _ => *array = Some(value.is_array()),
+            }
+        }
+    }
+
+    Ok(seen
+        .into_iter()
+        .map(|(name, (kind, array))| {
+            let field = InferredField {
+                kind,
+                array: array.unwrap_or_default(),
+            };
+
+            (name.to_string(), field)
+        })
+        .collect())
+}
+
+/// A field inferred from the documents.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) struct InferredField {
+    pub(crate) kind: FieldKind,
+
+    /// If the field holds arrays of values rather than a single value.
+    pub(crate) array: bool,
+}
+
+/// The type of a field as seen across the sampled documents.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub(crate) enum FieldKind {
+    /// Only nulls or empty arrays have been seen so far.
+    Empty,
+    Text,
+    Integer,
+    Float,
+    Bool,
+
+    /// The values can't be mapped to a single type.
+    Unsupported,
+}
+
+impl FieldKind {
+    fn of(value: &Value) -> Self {
+        match value {
+            Value::Null => Self::Empty,
+            Value::String(_) => Self::Text,
+            Value::Number(n) if n.is_f64() => Self::Float,
+            Value::Number(_) => Self::Integer,
+            Value::Bool(_) => Self::Bool,
+            Value::Array(values) => values
+                .iter()
+                .map(|v| match v {
+                    Value::Array(_) => Self::Unsupported,
+                    v => Self::of(v),
+                })
+                .fold(Self::Empty, Self::merge),
+            Value::Object(_) => Self::Unsupported,
+        }
+    }
+
+    fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Empty, kind) | (kind, Self::Empty) => kind,
+            (Self::Integer, Self::Float) | (Self::Float, Self::Integer) => Self::Float,
+            (a, b) if a == b => a,
+            _ => Self::Unsupported,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn infers_fields_from_documents() {
+        let docs = json!([
+            { "id": "1", "title": "Up", "genres": ["Animation"], "release_date": 1242345600, "rating": 8 },
+            { "id": "2", "title": "Heat", "genres": [], "release_date": null, "rating": 7.5, "adult": false },
+        ]);
+
+        let schema = IndexSchema::infer(docs.as_array().unwrap()).unwrap();
+
+        assert_eq!(
+            Value::Object(schema.fields),
+            json!({
+                "genres": { "type": "text", "stored": true },
+                "id": { "type": "string", "stored": true },
+                "rating": { "type": "f64", "stored": true, "indexed": false, "fast": true },
+                "release_date": { "type": "i64", "stored": true, "indexed": false, "fast": true },
+                "title": { "type": "text", "stored": true },
+            })
+        );
+        assert_eq!(schema.search_fields, vec!["genres", "title"]);
+    }
+}
@@ -1,34 +1,218 @@
 use std::future::Future;
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
+use chrono::Utc;
+use reqwest::{IntoUrl, Method, RequestBuilder};
+use serde_json::Value;
 
 use crate::sampler::SamplerHandle;
+use crate::timing::{InstrumentedClient, PhaseTimings};
 
-pub(crate) type RequestClient = reqwest::Client;
 pub(crate) type TargetUri = Arc<String>;
 pub(crate) type Query = String;
 
-fn get_client_and_addr(address: Arc<String>, index: &str) -> (RequestClient, TargetUri) {
+/// The options shared by every worker's client.
+#[derive(Debug, Default, Copy, Clone)]
+pub(crate) struct ClientOptions {
+    /// Parse and validate each successful search response body.
+    pub(crate) parse_responses: bool,
+
+    /// Send searches through the instrumented client to record the time
+    /// spent in each phase of the request.
+    pub(crate) phase_timing: bool,
+
+    /// Open a new connection for every request rather than keeping
+    /// connections alive.
+    pub(crate) new_connection_per_request: bool,
+}
+
+/// The HTTP client used by the workers to send searches.
+#[derive(Clone)]
+pub(crate) struct RequestClient {
+    inner: reqwest::Client,
+    instrumented: Option<InstrumentedClient>,
+    options: ClientOptions,
+}
+
+impl RequestClient {
+    pub(crate) fn new(options: ClientOptions) -> Self {
+        let mut builder = reqwest::Client::builder();
+        if options.new_connection_per_request {
+            builder = builder.pool_max_idle_per_host(0);
+        }
+
+        let instrumented = if options.phase_timing {
+            Some(InstrumentedClient::new(options.new_connection_per_request))
+        } else {
+            None
+        };
+
+        Self {
+            inner: builder.build().expect("build default client"),
+            instrumented,
+            options,
+        }
+    }
+
+    pub(crate) fn get<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.inner.get(url)
+    }
+
+    pub(crate) fn post<U: IntoUrl>(&self, url: U) -> RequestBuilder {
+        self.inner.post(url)
+    }
+
+    pub(crate) fn request<U: IntoUrl>(&self, method: Method, url: U) -> RequestBuilder {
+        self.inner.request(method, url)
+    }
+
+    /// Sends the search request and reads the response body.
+    ///
+    /// If response parsing is enabled the body of a successful response is
+    /// decoded as JSON and passed to `parse` to validate it.
+    pub(crate) async fn send_search<P>(
+        &self,
+        request: RequestBuilder,
+        success_status: &[u16],
+        parse: P,
+    ) -> Result<SearchResult>
+    where
+        P: FnOnce(&Value) -> Result<ParsedResponse, String>,
+    {
+        let (status, body, phases) = match self.instrumented.as_ref() {
+            Some(client) => {
+                let (status, body, phases) = client.send(request.build()?).await?;
+                (status, body, Some(phases))
+            },
+            None => {
+                let r = request.send().await?;
+                let status = r.status().as_u16();
+                (status, r.bytes().await?, None)
+            },
+        };
+
+        let success = success_status.contains(&status);
+        let parsed = if success && self.options.parse_responses {
+            let result = serde_json::from_slice(&body)
+                .map_err(|e| format!("invalid JSON body: {}", e))
+                .and_then(|data: Value| parse(&data));
+
+            Some(result)
+        } else {
+            None
+        };
+
+        Ok(SearchResult {
+            status,
+            bytes: body.len(),
+            success,
+            parsed,
+            phases,
+        })
+    }
+}
+
+/// The details extracted from a valid search response body.
+pub(crate) struct ParsedResponse {
+    /// The number of hits returned.
+    pub(crate) hits: usize,
+
+    /// The time the server reports it spent processing the search.
+    pub(crate) server_time: Option<Duration>,
+}
+
+/// The outcome of a single search request.
+pub(crate) struct SearchResult {
+    /// The status code returned by the server.
+    pub(crate) status: u16,
+
+    /// The size of the response body.
+    pub(crate) bytes: usize,
+
+    /// If the target considers the response a successful search.
+    pub(crate) success: bool,
+
+    /// The parsed response body or the reason it failed validation,
+    /// this is `None` if response parsing is disabled.
+    pub(crate) parsed: Option<Result<ParsedResponse, String>>,
+
+    /// The time spent in each phase of the request, this is `None` if
+    /// phase timing is disabled.
+    pub(crate) phases: Option<PhaseTimings>,
+}
+
+/// Gets the number at the given JSON pointer as a duration in the
+/// given unit of seconds, e.g. `0.001` for milliseconds.
+pub(crate) fn expect_duration(data: &Value, pointer: &str, unit: f64) -> Result<Duration, String> {
+    data.pointer(pointer)
+        .and_then(|v| v.as_f64())
+        .filter(|v| *v >= 0.0)
+        .map(|v| Duration::from_secs_f64(v * unit))
+        .ok_or_else(|| format!("missing {:?} number", pointer))
+}
+
+/// Gets the array at the given JSON pointer, describing what is missing
+/// if it doesn't exist.
+pub(crate) fn expect_array<'a>(data: &'a Value, pointer: &str) -> Result<&'a Vec<Value>, String> {
+    data.pointer(pointer)
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| format!("missing {:?} array", pointer))
+}
+
+/// The queries sent when typing out the term a character at a time.
+pub fn typing_prefixes(term: &str) -> impl Iterator<Item = String> + '_ {
+    term.char_indices()
+        .map(move |(pos, c)| term[..pos + c.len_utf8()].to_string())
+}
+
+fn get_client_and_addr(
+    address: Arc<String>,
+    index: &str,
+    options: ClientOptions,
+) -> (RequestClient, TargetUri) {
     let search_addr = Arc::new(format!("{}/indexes/{}/search", address, index));
-    let client = reqwest::Client::new();
+    let client = RequestClient::new(options);
 
     (client, search_addr)
 }
 
-pub(crate) async fn start_standard<T: Future<Output = Result<u16>>>(
+/// The terms a worker searches, these are repeated until the deadline if
+/// the run has a fixed duration.
+fn worker_terms<'a>(
+    terms: &'a [String],
+    sample: &SamplerHandle,
+) -> Box<dyn Iterator<Item = &'a String> + Send + 'a> {
+    if sample.has_deadline() {
+        Box::new(terms.iter().cycle())
+    } else {
+        Box::new(terms.iter())
+    }
+}
+
+pub(crate) async fn start_standard<F, T>(
     address: Arc<String>,
     mut sample: SamplerHandle,
     terms: Vec<String>,
     index: &str,
-    callback: fn(RequestClient, TargetUri, Query) -> T,
-) -> Result<()> {
-    let (client, search_addr) = get_client_and_addr(address, index);
+    options: ClientOptions,
+    callback: F,
+) -> Result<()>
+where
+    F: Fn(RequestClient, TargetUri, Query) -> T,
+    T: Future<Output = Result<SearchResult>>,
+{
+    let (client, search_addr) = get_client_and_addr(address, index, options);
+
+    for term in worker_terms(&terms, &sample) {
+        if sample.expired() {
+            break;
+        }
 
-    for term in terms.iter() {
+        let timestamp = Utc::now();
         let start = Instant::now();
-         let status = match callback(client.clone(), search_addr.clone(), term.clone()).await {
+        let result = match callback(client.clone(), search_addr.clone(), term.clone()).await {
             Ok(s) => s,
             Err(e) => {
                 sample.finish();
@@ -37,36 +221,51 @@ pub(crate) async fn start_standard<T: Future<Output = Result<u16>>>(
         };
         let stop = start.elapsed();
 
-        if status != 200 {
-            sample.register_error(status);
+        sample.log_request(timestamp, term, None, &result, stop);
+        sample.add_query(term, result.status, stop);
+        sample.add_status_latency(result.status, stop);
+        if !result.success {
+            sample.register_error(result.status);
         } else {
             sample.add_latency(stop);
         }
+
+        if let Some(parsed) = result.parsed {
+            sample.register_response(parsed, stop);
+        }
+
+        if let Some(phases) = result.phases {
+            sample.add_phase_timings(phases);
+        }
     }
 
     sample.finish();
     Ok(())
 }
 
-pub(crate) async fn start_typing<T: Future<Output = Result<u16>>>(
+pub(crate) async fn start_typing<F, T>(
     address: Arc<String>,
     mut sample: SamplerHandle,
     terms: Vec<String>,
     index: &str,
-    callback: fn(RequestClient, TargetUri, Query) -> T,
-) -> Result<()> {
-    let (client, search_addr) = get_client_and_addr(address, index);
-
-    for term in terms.iter() {
-        let mut chars = term.chars();
-        let mut search_term = vec![];
-        while let Some(c) = chars.next() {
-            search_term.push(c);
+    options: ClientOptions,
+    callback: F,
+) -> Result<()>
+where
+    F: Fn(RequestClient, TargetUri, Query) -> T,
+    T: Future<Output = Result<SearchResult>>,
+{
+    let (client, search_addr) = get_client_and_addr(address, index, options);
 
-            let query: String = search_term.iter().collect();
+    'terms: for term in worker_terms(&terms, &sample) {
+        for (pos, query) in typing_prefixes(term).enumerate() {
+            if sample.expired() {
+                break 'terms;
+            }
 
+            let timestamp = Utc::now();
             let start = Instant::now();
-            let status = match callback(client.clone(), search_addr.clone(), query).await {
+            let result = match callback(client.clone(), search_addr.clone(), query.clone()).await {
                 Ok(s) => s,
                 Err(e) => {
                     sample.finish();
@@ -75,11 +274,22 @@ pub(crate) async fn start_typing<T: Future<Output = Result<u16>>>(
             };
             let stop = start.elapsed();
 
-            if status != 200 {
-                sample.register_error(status);
+            sample.log_request(timestamp, &query, Some(pos + 1), &result, stop);
+            sample.add_query(&query, result.status, stop);
+            sample.add_status_latency(result.status, stop);
+            if !result.success {
+                sample.register_error(result.status);
             } else {
                 sample.add_latency(stop);
-                sample.add_latency_for_sentence_length(search_term.len(), stop);
+                sample.add_latency_for_sentence_length(pos + 1, stop);
+            }
+
+            if let Some(parsed) = result.parsed {
+                sample.register_response(parsed, stop);
+            }
+
+            if let Some(phases) = result.phases {
+                sample.add_phase_timings(phases);
             }
         }
     }
@@ -0,0 +1,321 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use plotters::style::{RGBColor, BLUE, CYAN, GREEN, MAGENTA, RED, YELLOW};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::charts::{self, ChartOptions};
+use crate::histogram::LatencyHistogram;
+
+/// The time spent in each phase of the requests, the connection phases
+/// are only recorded for requests which opened a new connection.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub(crate) struct PhaseHistograms {
+    pub(crate) dns: LatencyHistogram,
+    pub(crate) connect: LatencyHistogram,
+    pub(crate) tls: LatencyHistogram,
+    pub(crate) ttfb: LatencyHistogram,
+    pub(crate) body: LatencyHistogram,
+}
+
+impl PhaseHistograms {
+    fn add(&mut self, other: &Self) -> anyhow::Result<()> {
+        self.dns.add(&other.dns)?;
+        self.connect.add(&other.connect)?;
+        self.tls.add(&other.tls)?;
+        self.ttfb.add(&other.ttfb)?;
+        self.body.add(&other.body)?;
+
+        Ok(())
+    }
+}
+
+/// The number of slowest requests kept for the report.
+const SLOWEST_QUERIES: usize = 25;
+
+/// A single slow request.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct SlowQuery {
+    pub(crate) query: String,
+    pub(crate) status: u16,
+    pub(crate) latency_micros: u64,
+}
+
+/// A named histogram drawn as a line on the percentile charts.
+pub(crate) type PercentileSeries<'a> = (&'a str, &'a LatencyHistogram, RGBColor);
+
+/// The results of a benchmark run.
+///
+/// Each worker records into its own summary which are merged once the
+/// run completes, the merged summary is saved so the stats and charts can
+/// be regenerated later without re-running the benchmark.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub(crate) struct RunSummary {
+    /// All successful request latencies.
+    pub(crate) latencies: LatencyHistogram,
+
+    /// The successful request latencies for each sentence length.
+    pub(crate) sentence_length_latencies: Vec<LatencyHistogram>,
+
+    /// The latencies of every request grouped by the returned status.
+    pub(crate) status_latencies: HashMap<u16, LatencyHistogram>,
+
+    pub(crate) errors: HashMap<u16, usize>,
+
+    pub(crate) requests_second: f64,
+
+    /// The number of response bodies which passed validation.
+    pub(crate) valid_responses: usize,
+
+    /// The number of valid responses which returned no hits.
+    pub(crate) zero_results: usize,
+
+    /// The total number of hits across all valid responses.
+    pub(crate) total_hits: u64,
+
+    /// The reasons responses failed validation and how often each occurred.
+    pub(crate) invalid_responses: HashMap<String, usize>,
+
+    /// The processing times reported by the server.
+    pub(crate) server_latencies: LatencyHistogram,
+
+    /// The client latency minus the server reported time, this is the
+    /// transport and serialization overhead of each request.
+    pub(crate) overhead_latencies: LatencyHistogram,
+
+    /// The time spent in each phase of the requests.
+    pub(crate) phases: PhaseHistograms,
+
+    /// The slowest requests of the run, slowest first.
+    #[serde(default)]
+    pub(crate) slowest_queries: Vec<SlowQuery>,
+
+    /// The settings the benchmark was run with as name and value pairs.
+    #[serde(default)]
+    pub(crate) config: Vec<(String, String)>,
+}
+
+impl RunSummary {
+    pub(crate) fn add_latency_for_sentence_length(&mut self, length: usize, dur: Duration) {
+        while self.sentence_length_latencies.len() <= length {
+            self.sentence_length_latencies.push(LatencyHistogram::new());
+        }
+
+        self.sentence_length_latencies[length].record(dur);
+    }
+
+    pub(crate) fn add_status_latency(&mut self, status: u16, dur: Duration) {
+        self.status_latencies.entry(status).or_default().record(dur);
+    }
+
+    /// Keeps the request if it is one of the slowest seen so far.
+    pub(crate) fn add_query(&mut self, query: &str, status: u16, dur: Duration) {
+        let latency_micros = dur.as_micros() as u64;
+        let is_slow = self.slowest_queries.len() < SLOWEST_QUERIES
+            || self
+                .slowest_queries
+                .last()
+                .map(|q| latency_micros > q.latency_micros)
+                .unwrap_or(true);
+
+        if is_slow {
+            let pos = self
+                .slowest_queries
+                .partition_point(|q| q.latency_micros >= latency_micros);
+            self.slowest_queries.insert(pos, SlowQuery {
+                query: query.to_string(),
+                status,
+                latency_micros,
+            });
+            self.slowest_queries.truncate(SLOWEST_QUERIES);
+        }
+    }
+
+    /// The latency line of every successful request.
+    pub(crate) fn latency_series(&self) -> [PercentileSeries<'_>; 1] {
+        [("Latency", &self.latencies, RED)]
+    }
+
+    /// The client, server and overhead latency lines.
+    pub(crate) fn breakdown_series(&self) -> [PercentileSeries<'_>; 3] {
+        [
+            ("Client", &self.latencies, RED),
+            ("Server", &self.server_latencies, BLUE),
+            ("Overhead", &self.overhead_latencies, GREEN),
+        ]
+    }
+
+    /// The request phase latency lines.
+    pub(crate) fn phase_series(&self) -> [PercentileSeries<'_>; 5] {
+        let phases = &self.phases;
+        [
+            ("DNS", &phases.dns, MAGENTA),
+            ("Connect", &phases.connect, CYAN),
+            ("TLS", &phases.tls, YELLOW),
+            ("TTFB", &phases.ttfb, BLUE),
+            ("Body", &phases.body, GREEN),
+        ]
+    }
+
+    /// Merges the other summary into this one.
+    ///
+    /// The requests per second are summed as the runs are expected to
+    /// have happened concurrently.
+    pub(crate) fn add(&mut self, other: &Self) -> anyhow::Result<()> {
+        self.latencies.add(&other.latencies)?;
+
+        for (length, latencies) in other.sentence_length_latencies.iter().enumerate() {
+            if length >= self.sentence_length_latencies.len() {
+                self.sentence_length_latencies.push(LatencyHistogram::new());
+            }
+
+            self.sentence_length_latencies[length].add(latencies)?;
+        }
+
+        for (status, latencies) in other.status_latencies.iter() {
+            self.status_latencies.entry(*status).or_default().add(latencies)?;
+        }
+
+        for (status, count) in other.errors.iter() {
+            *self.errors.entry(*status).or_default() += count;
+        }
+
+        self.requests_second += other.requests_second;
+        self.valid_responses += other.valid_responses;
+        self.zero_results += other.zero_results;
+        self.total_hits += other.total_hits;
+        for (reason, count) in other.invalid_responses.iter() {
+            *self.invalid_responses.entry(reason.clone()).or_default() += count;
+        }
+
+        self.server_latencies.add(&other.server_latencies)?;
+        self.overhead_latencies.add(&other.overhead_latencies)?;
+        self.phases.add(&other.phases)?;
+
+        self.slowest_queries.extend(other.slowest_queries.iter().cloned());
+        self.slowest_queries
+            .sort_by_key(|q| Reverse(q.latency_micros));
+        self.slowest_queries.truncate(SLOWEST_QUERIES);
+
+        Ok(())
+    }
+
+    pub(crate) async fn save(&self, path: &str) -> anyhow::Result<()> {
+        fs::write(path, serde_json::to_vec(self)?).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn load(path: &str) -> anyhow::Result<Self> {
+        let data = fs::read(path).await?;
+        Ok(serde_json::from_slice(&data)?)
+    }
+
+    /// Logs the stats of the run and draws the charts into the output directory.
+    pub(crate) fn render(&self, output: &str, options: &ChartOptions) -> anyhow::Result<()> {
+        let hist = &self.latencies;
+
+        info!("General benchmark results:");
+        info!("     Total Succesful Requests Sent: {}", hist.len());
+        info!("     Average Requests/sec: {:.2}", self.requests_second);
+        info!("     Average Latency: {:?}", Duration::from_secs_f64(hist.mean() / (1000f64.powf(2.0))));
+        info!("     Max Latency: {:?}", Duration::from_micros(hist.max()));
+        info!("     Min Latency: {:?}", Duration::from_micros(hist.min()));
+        info!("     Stdev Latency: {:?}", Duration::from_secs_f64(hist.stdev() / (1000f64.powf(2.0))));
+
+        for (code, amount) in self.errors.iter() {
+            warn!("     Got status {}: {}", code, amount);
+        }
+
+        if self.status_latencies.len() > 1 {
+            let mut statuses: Vec<_> = self.status_latencies.iter().collect();
+            statuses.sort_by_key(|(status, _)| **status);
+
+            info!("Latency by status results:");
+            for (status, latencies) in statuses {
+                log_percentiles(&format!("Status {}", status), latencies, options);
+            }
+        }
+
+        let invalid_total = self.invalid_responses.values().sum::<usize>();
+        if self.valid_responses + invalid_total > 0 {
+            let zero_result_rate = if self.valid_responses == 0 {
+                0.0
+            } else {
+                self.zero_results as f64 / self.valid_responses as f64 * 100.0
+            };
+            let avg_hits = if self.valid_responses == 0 {
+                0.0
+            } else {
+                self.total_hits as f64 / self.valid_responses as f64
+            };
+
+            info!("Response validation results:");
+            info!("     Valid Responses: {}", self.valid_responses);
+            info!("     Zero Result Rate: {:.2}%", zero_result_rate);
+            info!("     Average Hits: {:.2}", avg_hits);
+            info!("     Schema Validation Failures: {}", invalid_total);
+
+            for (reason, amount) in self.invalid_responses.iter() {
+                warn!("     Invalid response ({}): {}", reason, amount);
+            }
+        }
+
+        if !self.server_latencies.is_empty() {
+            info!("Server vs client latency results:");
+            log_percentiles("Client", hist, options);
+            log_percentiles("Server", &self.server_latencies, options);
+            log_percentiles("Overhead", &self.overhead_latencies, options);
+
+            let breakdown_output = format!("{}/latency-breakdown.png", output);
+            charts::save_png(&breakdown_output, options, |root| {
+                charts::percentile_lines(
+                    root,
+                    options,
+                    "Server vs Client Latency",
+                    &self.breakdown_series(),
+                )
+            })?;
+            info!("Latency breakdown has been saved to {}", breakdown_output);
+        }
+
+        let phases = &self.phases;
+        if !phases.ttfb.is_empty() {
+            info!("Phase timing results:");
+            info!("     New Connections Opened: {}", phases.connect.len());
+            log_percentiles("DNS", &phases.dns, options);
+            log_percentiles("Connect", &phases.connect, options);
+            if !phases.tls.is_empty() {
+                log_percentiles("TLS", &phases.tls, options);
+            }
+            log_percentiles("TTFB", &phases.ttfb, options);
+            log_percentiles("Body", &phases.body, options);
+
+            let phase_output = format!("{}/phase-timings.png", output);
+            charts::save_png(&phase_output, options, |root| {
+                charts::percentile_lines(root, options, "Request Phase Latency", &self.phase_series())
+            })?;
+            info!("Phase timings have been saved to {}", phase_output);
+        }
+
+        let run_output = format!("{}/run-output.png", output);
+        charts::save_png(&run_output, options, |root| {
+            charts::sentence_lengths(root, &self.sentence_length_latencies)
+        })?;
+        info!("Result has been saved to {}", run_output);
+
+        Ok(())
+    }
+}
+
+fn log_percentiles(name: &str, hist: &LatencyHistogram, options: &ChartOptions) {
+    let percentiles = options
+        .percentiles
+        .iter()
+        .map(|p| format!("p{}={:?}", p, hist.percentile(*p)))
+        .collect::<Vec<String>>()
+        .join(" ");
+
+    info!("     {} Latency: {}", name, percentiles);
+}
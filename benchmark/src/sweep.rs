@@ -0,0 +1,207 @@
+use std::fs;
+
+use anyhow::anyhow;
+
+use crate::charts::{self, ChartOptions, Heatmap};
+use crate::index_sweep;
+use crate::scenario::{ScenarioResult, SweepOptions};
+use crate::{BenchMode, Context};
+
+/// Runs the benchmark for every combination of the swept values.
+pub(crate) fn run(base: Context, sweep: &SweepOptions) -> anyhow::Result<Vec<ScenarioResult>> {
+    match sweep.index_settings.as_ref() {
+        Some(grid) => index_sweep::run(base, sweep, grid),
+        None => run_matrix(base, sweep),
+    }
+}
+
+/// Runs the benchmark for every combination of the swept concurrency,
+/// threads and modes.
+///
+/// The index is prepped once before the first combination, each combination
+/// is then a separate run with its own sampler which writes
+/// its results to a directory within the output directory, the matrix of
+/// results is logged and saved along with a heatmap of the p99 latency
+/// and throughput.
+pub(crate) fn run_matrix(base: Context, sweep: &SweepOptions) -> anyhow::Result<Vec<ScenarioResult>> {
+    let concurrency = swept(&sweep.concurrency, base.concurrency, "concurrency")?;
+    let threads = swept(&sweep.threads, base.threads, "threads")?;
+    let modes = swept(&sweep.mode, base.mode, "mode")?;
+
+    if concurrency.contains(&0) || threads.contains(&0) {
+        return Err(anyhow!("the swept concurrency and threads must be greater than 0"));
+    }
+
+    // The index is prepped once and shared by every cell so the documents
+    // are only ingested once and each cell searches the same index.
+    let created_index = crate::prep_shared_index(&base)?;
+    let results = run_cells(&base, &modes, &concurrency, &threads);
+    crate::teardown_shared_index(&base, created_index, results.is_err())?;
+    let results = results?;
+
+    let matrix = Matrix {
+        modes: &modes,
+        concurrency: &concurrency,
+        threads: &threads,
+        results: &results,
+    };
+    matrix.log();
+
+    let data = format!("{}/sweep.json", base.output);
+    fs::write(&data, serde_json::to_vec_pretty(&results)?)?;
+    info!("Sweep results have been saved to {}", data);
+
+    let chart = format!("{}/sweep-heatmap.png", base.output);
+    let options = ChartOptions {
+        size: (1920, 600 * modes.len() as u32),
+        ..ChartOptions::default()
+    };
+    charts::save_png(&chart, &options, |root| {
+        charts::heatmaps(root, "Sweep Results", &matrix.heatmaps())
+    })?;
+    info!("Sweep heatmap has been saved to {}", chart);
+
+    Ok(results)
+}
+
+/// Runs every combination against the index prepped by the caller.
+fn run_cells(
+    base: &Context,
+    modes: &[BenchMode],
+    concurrency: &[usize],
+    threads: &[usize],
+) -> anyhow::Result<Vec<ScenarioResult>> {
+    let total = modes.len() * concurrency.len() * threads.len();
+    let mut results = vec![];
+    for mode in modes.iter() {
+        for c in concurrency.iter() {
+            for t in threads.iter() {
+                let name = format!("{}-c{}-t{}", mode_name(*mode), c, t);
+                let output = format!("{}/{}", base.output, name);
+                fs::create_dir_all(&output)?;
+
+                info!("running sweep {} ({}/{})", name, results.len() + 1, total);
+
+                // Every cell keeps its own raw log rather than overwriting
+                // the previous cell's.
+                let ctx = Context {
+                    concurrency: *c,
+                    threads: *t,
+                    mode: *mode,
+                    no_prep: true,
+                    raw_log: base.raw_log.as_ref().map(|_| format!("{}/raw-log.csv", output)),
+                    output,
+                    ..base.clone()
+                };
+
+                let mut result = ScenarioResult::new(name, &ctx);
+                let summary = crate::run_summarised(ctx)
+                    .map_err(|e| anyhow!("sweep {} failed: {}", result.name, e))?;
+                result.record(&summary);
+                results.push(result);
+            }
+        }
+    }
+
+    Ok(results)
+}
+
+/// The swept values, or the base value if it isn't swept.
+fn swept<T: Copy>(values: &Option<Vec<T>>, base: T, name: &str) -> anyhow::Result<Vec<T>> {
+    match values {
+        Some(values) if values.is_empty() => Err(anyhow!("the swept {} values can't be empty", name)),
+        Some(values) => Ok(values.clone()),
+        None => Ok(vec![base]),
+    }
+}
+
+fn mode_name(mode: BenchMode) -> String {
+    format!("{:?}", mode).to_lowercase()
+}
+
+/// Gets one of the headline numbers from a result.
+type Metric = fn(&ScenarioResult) -> f64;
+
+/// The results of each combination laid out as a concurrency by threads
+/// matrix for each mode.
+struct Matrix<'a> {
+    modes: &'a [BenchMode],
+    concurrency: &'a [usize],
+    threads: &'a [usize],
+    results: &'a [ScenarioResult],
+}
+
+impl<'a> Matrix<'a> {
+    fn get(&self, mode: BenchMode, concurrency: usize, threads: usize) -> Option<&'a ScenarioResult> {
+        let mode = format!("{:?}", mode);
+        self.results
+            .iter()
+            .find(|r| r.mode == mode && r.concurrency == concurrency && r.threads == threads)
+    }
+
+    /// The value of every cell for the mode row by row.
+    fn values(&self, mode: BenchMode, value: Metric) -> Vec<Option<f64>> {
+        self.concurrency
+            .iter()
+            .flat_map(|c| self.threads.iter().map(move |t| (*c, *t)))
+            .map(|(c, t)| self.get(mode, c, t).map(value))
+            .collect()
+    }
+
+    fn log(&self) {
+        let metrics: [(&str, Metric); 2] = [
+            ("p99 Latency (ms)", |r| r.p99_latency_ms),
+            ("Requests/sec", |r| r.requests_second),
+        ];
+
+        info!("Sweep results:");
+        for mode in self.modes.iter() {
+            for (name, value) in metrics.iter() {
+                info!("     {} of {} mode, concurrency by threads:", name, mode_name(*mode));
+
+                let header: String = self.threads.iter().map(|t| format!("{:>12}", t)).collect();
+                info!("     {:>11}{}", "", header);
+
+                let values = self.values(*mode, *value);
+                for (c, row) in self.concurrency.iter().zip(values.chunks(self.threads.len())) {
+                    let row: String = row
+                        .iter()
+                        .map(|v| match v {
+                            Some(v) => format!("{:>12.2}", v),
+                            None => format!("{:>12}", "-"),
+                        })
+                        .collect();
+                    info!("     {:>11}{}", c, row);
+                }
+            }
+        }
+    }
+
+    fn heatmaps(&self) -> Vec<Heatmap<'a>> {
+        let mut maps = vec![];
+        for mode in self.modes.iter() {
+            maps.push(Heatmap {
+                caption: format!("p99 Latency (ms), {} mode", mode_name(*mode)),
+                row_desc: "Concurrency",
+                rows: self.concurrency,
+                column_desc: "Threads",
+                columns: self.threads,
+                values: self.values(*mode, |r| r.p99_latency_ms),
+                label: |v| format!("{:.2}ms", v),
+                higher_is_better: false,
+            });
+            maps.push(Heatmap {
+                caption: format!("Requests/sec, {} mode", mode_name(*mode)),
+                row_desc: "Concurrency",
+                rows: self.concurrency,
+                column_desc: "Threads",
+                columns: self.threads,
+                values: self.values(*mode, |r| r.requests_second),
+                label: |v| format!("{:.1}", v),
+                higher_is_better: true,
+            });
+        }
+
+        maps
+    }
+}
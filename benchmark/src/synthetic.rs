@@ -0,0 +1,557 @@
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::{Map, Value};
+
+use crate::dataset::{self, Compression, Format};
+use crate::GenDataContext;
+
+/// The number of distinct tags a `tags` field picks from.
+const TAG_VOCABULARY_SIZE: usize = 32;
+
+/// How often the progress is logged while writing the documents.
+const PROGRESS_INTERVAL: u64 = 1_000_000;
+
+/// The syllables the vocabulary words are made of, every word is at least
+/// two syllables long.
+const CONSONANTS: &[u8] = b"bcdfghklmnprstvz";
+const VOWELS: &[u8] = b"aeiou";
+
+/// A generated field of the synthetic documents, e.g. `title:text(3..8 words)`.
+///
+/// The ranges are inclusive and optional, the kinds are:
+///  - `text(min..max words)` words drawn from the vocabulary, 3 to 8 by default.
+///  - `tags(min..max tags)` a list of distinct tags, 1 to 3 by default.
+///  - `i64(min..max)` a random integer, 0 to 2,000,000,000 by default.
+///  - `f64(min..max)` a random number with two decimals, 0 to 10 by default.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldSpec {
+    name: String,
+    kind: FieldKind,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum FieldKind {
+    Text { min: usize, max: usize },
+    Tags { min: usize, max: usize },
+    I64 { min: i64, max: i64 },
+    F64 { min: f64, max: f64 },
+}
+
+impl FromStr for FieldSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, kind) = s.trim().split_once(':').ok_or_else(|| {
+            format!(
+                "invalid field {:?}, expected a name and kind like 'title:text'",
+                s
+            )
+        })?;
+
+        if name.is_empty() {
+            return Err(format!("the field {:?} has no name", s));
+        }
+        if name == "id" {
+            return Err("the id field is always generated and can't be given".to_string());
+        }
+
+        let (kind_name, args) = match kind.split_once('(') {
+            Some((kind_name, args)) => {
+                let args = args
+                    .strip_suffix(')')
+                    .ok_or_else(|| format!("the field {:?} is missing a closing ')'", s))?;
+                (kind_name, Some(args))
+            },
+            None => (kind, None),
+        };
+
+        let kind = match kind_name {
+            "text" => {
+                let (min, max) = parse_range(s, args, Some("words"), (3, 8))?;
+                FieldKind::Text { min, max }
+            },
+            "tags" => {
+                let (min, max) = parse_range(s, args, Some("tags"), (1, 3))?;
+                if max > TAG_VOCABULARY_SIZE {
+                    return Err(format!(
+                        "the field {:?} can have at most {} tags",
+                        s, TAG_VOCABULARY_SIZE
+                    ));
+                }
+                FieldKind::Tags { min, max }
+            },
+            "i64" => {
+                let (min, max) = parse_range(s, args, None, (0, 2_000_000_000))?;
+                FieldKind::I64 { min, max }
+            },
+            "f64" => {
+                let (min, max) = parse_range(s, args, None, (0.0, 10.0))?;
+                FieldKind::F64 { min, max }
+            },
+            other => return Err(format!(
+                "unknown field kind {:?} in {:?}, expected one of 'text', 'tags', 'i64' or 'f64'",
+                other, s
+            )),
+        };
+
+        Ok(Self {
+            name: name.to_string(),
+            kind,
+        })
+    }
+}
+
+/// Parses an inclusive `min..max` range followed by an optional unit, e.g.
+/// `3..8 words`.
+fn parse_range<T>(
+    field: &str,
+    args: Option<&str>,
+    unit: Option<&str>,
+    default: (T, T),
+) -> Result<(T, T), String>
+where
+    T: FromStr + PartialOrd,
+{
+    let args = match args {
+        Some(args) => args,
+        None => return Ok(default),
+    };
+
+    let mut parts = args.split_whitespace();
+    let range = parts.next().unwrap_or_default();
+    match (parts.next(), unit) {
+        (None, _) => {},
+        (Some(given), Some(unit)) if given == unit => {},
+        (Some(given), _) => return Err(format!("unexpected {:?} in the field {:?}", given, field)),
+    }
+    if let Some(extra) = parts.next() {
+        return Err(format!("unexpected {:?} in the field {:?}", extra, field));
+    }
+
+    let invalid = || {
+        format!(
+            "invalid range in the field {:?}, expected 'min..max'",
+            field
+        )
+    };
+    let bound = |bound: &str| {
+        // Floats also parse NaN and infinities, which can't be sampled from.
+        if bound.parse::<f64>().is_ok_and(|bound| !bound.is_finite()) {
+            return Err(format!(
+                "the range in the field {:?} must be between finite numbers",
+                field
+            ));
+        }
+
+        bound.parse::<T>().map_err(|_| invalid())
+    };
+    let (min, max) = range.split_once("..").ok_or_else(invalid)?;
+    let (min, max) = (bound(min)?, bound(max)?);
+    if min > max {
+        return Err(format!(
+            "the range in the field {:?} has a minimum above its maximum",
+            field
+        ));
+    }
+
+    Ok((min, max))
+}
+
+/// Parses a count with an optional `k`, `M` or `B` suffix, e.g. `10M`.
+pub fn parse_count(s: &str) -> Result<u64, String> {
+    let invalid = || {
+        format!(
+            "invalid count {:?}, expected a number like 5000, 250k or 10M",
+            s
+        )
+    };
+    let s = s.trim();
+    let (number, multiplier) = match s.char_indices().last() {
+        Some((i, 'k')) | Some((i, 'K')) => (&s[..i], 1_000.0),
+        Some((i, 'm')) | Some((i, 'M')) => (&s[..i], 1_000_000.0),
+        Some((i, 'b')) | Some((i, 'B')) => (&s[..i], 1_000_000_000.0),
+        _ => return s.parse().map_err(|_| invalid()),
+    };
+
+    let count = number.parse::<f64>().map_err(|_| invalid())? * multiplier;
+    if !count.is_finite() || count < 0.0 || count.fract() != 0.0 {
+        return Err(invalid());
+    }
+
+    Ok(count as u64)
+}
+
+/// Words ranked by how often they are used, the chance of picking a word
+/// follows a Zipf distribution so a few words are very common and most are
+/// rare like in natural language.
+struct Vocabulary {
+    words: Vec<String>,
+
+    /// The running total of the word weights, this is searched to pick a
+    /// word from a uniformly random number.
+    cumulative: Vec<f64>,
+}
+
+impl Vocabulary {
+    fn new(size: usize, exponent: f64) -> Self {
+        let mut total = 0.0;
+        let cumulative = (1..=size)
+            .map(|rank| {
+                total += 1.0 / (rank as f64).powf(exponent);
+                total
+            })
+            .collect();
+
+        Self {
+            words: (0..size).map(word).collect(),
+            cumulative,
+        }
+    }
+
+    fn sample(&self, rng: &mut StdRng) -> &str {
+        let total = self.cumulative.last().copied().unwrap_or_default();
+        let target = rng.gen::<f64>() * total;
+        let rank = self
+            .cumulative
+            .partition_point(|v| *v <= target)
+            .min(self.words.len() - 1);
+
+        &self.words[rank]
+    }
+
+    /// Picks `count` distinct words without replacement, each picked word's
+    /// weight is removed before the next pick so even the rarest words are
+    /// picked in a bounded number of draws.
+    fn sample_distinct(&self, rng: &mut StdRng, count: usize) -> Vec<&str> {
+        let mut previous = 0.0;
+        let mut weights: Vec<f64> = self
+            .cumulative
+            .iter()
+            .map(|total| {
+                let weight = total - previous;
+                previous = *total;
+                weight
+            })
+            .collect();
+        let mut picked = vec![false; weights.len()];
+
+        let mut ranks = vec![];
+        while ranks.len() < count.min(weights.len()) {
+            let total: f64 = weights.iter().sum();
+
+            // A steep enough distribution leaves the rarest words with no
+            // weight, these are picked uniformly once every other word is.
+            let rank = if total > 0.0 {
+                let mut target = rng.gen::<f64>() * total;
+                let mut rank = weights.iter().rposition(|w| *w > 0.0).unwrap_or_default();
+                for (i, weight) in weights.iter().enumerate() {
+                    if *weight > 0.0 && target < *weight {
+                        rank = i;
+                        break;
+                    }
+                    target -= weight;
+                }
+                rank
+            } else {
+                let remaining: Vec<usize> = (0..picked.len()).filter(|i| !picked[*i]).collect();
+                remaining[rng.gen_range(0..remaining.len())]
+            };
+
+            weights[rank] = 0.0;
+            picked[rank] = true;
+            ranks.push(rank);
+        }
+
+        ranks.sort_unstable();
+        ranks.into_iter().map(|rank| self.words[rank].as_str()).collect()
+    }
+}
+
+/// The word for a rank, every rank gets a distinct word and the more common
+/// words are the shorter ones.
+fn word(rank: usize) -> String {
+    let syllables = CONSONANTS.len() * VOWELS.len();
+
+    // Skipping the single syllable words makes every word at least two
+    // syllables long.
+    let mut n = rank + syllables;
+    let mut word = vec![];
+    while n > 0 {
+        let syllable = n % syllables;
+        word.push(VOWELS[syllable % VOWELS.len()]);
+        word.push(CONSONANTS[syllable / VOWELS.len()]);
+        n /= syllables;
+    }
+
+    word.reverse();
+    String::from_utf8(word).unwrap_or_default()
+}
+
+struct Generator<'a> {
+    fields: &'a [FieldSpec],
+    words: Vocabulary,
+    tags: Vocabulary,
+    rng: StdRng,
+}
+
+impl<'a> Generator<'a> {
+    fn new(ctx: &'a GenDataContext) -> Self {
+        Self {
+            fields: &ctx.fields,
+            words: Vocabulary::new(ctx.vocabulary, ctx.zipf_exponent),
+            tags: Vocabulary::new(TAG_VOCABULARY_SIZE, ctx.zipf_exponent),
+            rng: StdRng::seed_from_u64(ctx.seed),
+        }
+    }
+
+    fn document(&mut self, id: u64) -> Value {
+        let mut doc = Map::new();
+        doc.insert("id".to_string(), Value::from(id.to_string()));
+
+        for field in self.fields {
+            let value = match field.kind {
+                FieldKind::Text { min, max } => {
+                    let len = self.rng.gen_range(min..=max);
+                    Value::from(self.text(len))
+                },
+                FieldKind::Tags { min, max } => {
+                    let len = self.rng.gen_range(min..=max);
+                    Value::from(self.tags.sample_distinct(&mut self.rng, len))
+                },
+                FieldKind::I64 { min, max } => Value::from(self.rng.gen_range(min..=max)),
+                FieldKind::F64 { min, max } => {
+                    let value = self.rng.gen_range(min..=max);
+                    Value::from((value * 100.0).round() / 100.0)
+                },
+            };
+
+            doc.insert(field.name.clone(), value);
+        }
+
+        Value::Object(doc)
+    }
+
+    /// A search query of one to three words drawn from the same
+    /// vocabulary as the documents.
+    fn query(&mut self) -> String {
+        let len = self.rng.gen_range(1..=3);
+        self.text(len)
+    }
+
+    fn text(&mut self, len: usize) -> String {
+        let mut text = String::new();
+        for i in 0..len {
+            if i > 0 {
+                text.push(' ');
+            }
+            text.push_str(self.words.sample(&mut self.rng));
+        }
+
+        text
+    }
+}
+
+/// The data file being written, compressed if its extension asks for it.
+enum Output {
+    Plain(BufWriter<File>),
+    Gzip(flate2::write::GzEncoder<BufWriter<File>>),
+    Zstd(zstd::stream::write::Encoder<'static, BufWriter<File>>),
+}
+
+impl Output {
+    fn create(path: &str, compression: Compression) -> anyhow::Result<Self> {
+        let file = File::create(path)
+            .map_err(|e| anyhow!("failed to create the data file {:?}: {}", path, e))?;
+        let file = BufWriter::new(file);
+
+        Ok(match compression {
+            Compression::None => Self::Plain(file),
+            Compression::Gzip => Self::Gzip(flate2::write::GzEncoder::new(
+                file,
+                flate2::Compression::default(),
+            )),
+            Compression::Zstd => Self::Zstd(zstd::stream::write::Encoder::new(file, 0)?),
+        })
+    }
+
+    fn writer(&mut self) -> &mut dyn Write {
+        match self {
+            Self::Plain(w) => w,
+            Self::Gzip(w) => w,
+            Self::Zstd(w) => w,
+        }
+    }
+
+    fn finish(self) -> anyhow::Result<()> {
+        let mut file = match self {
+            Self::Plain(w) => w,
+            Self::Gzip(w) => w.finish()?,
+            Self::Zstd(w) => w.finish()?,
+        };
+
+        Ok(file.flush()?)
+    }
+}
+
+/// Writes the synthetic documents to the data file and the matching
+/// queries to the search terms file.
+pub(crate) fn generate(ctx: GenDataContext) -> anyhow::Result<()> {
+    if ctx.vocabulary == 0 {
+        return Err(anyhow!("the vocabulary must have at least one word"));
+    }
+    if !ctx.zipf_exponent.is_finite() || ctx.zipf_exponent < 0.0 {
+        return Err(anyhow!("the Zipf exponent must be a number of 0 or more"));
+    }
+
+    let mut names = BTreeSet::new();
+    if let Some(field) = ctx.fields.iter().find(|f| !names.insert(f.name.as_str())) {
+        return Err(anyhow!(
+            "the field {:?} is given more than once",
+            field.name
+        ));
+    }
+
+    let (format, compression) = dataset::detect(&ctx.output)?;
+    let is_array = match format {
+        None => true,
+        Some(Format::JsonLines) => false,
+        Some(Format::JsonArray) | Some(Format::Csv) => {
+            return Err(anyhow!(
+                "synthetic documents can only be written to a .json or .jsonl file, the tags fields can't be held by a CSV file"
+            ))
+        },
+    };
+
+    let mut generator = Generator::new(&ctx);
+    let mut output = Output::create(&ctx.output, compression)?;
+    let writer = output.writer();
+
+    if is_array {
+        writer.write_all(b"[\n")?;
+    }
+    for id in 1..=ctx.docs {
+        if is_array && id > 1 {
+            writer.write_all(b",\n")?;
+        }
+        serde_json::to_writer(&mut *writer, &generator.document(id))?;
+        if !is_array {
+            writer.write_all(b"\n")?;
+        }
+
+        if id % PROGRESS_INTERVAL == 0 {
+            info!("generated {} of {} documents", id, ctx.docs);
+        }
+    }
+    if is_array {
+        writer.write_all(b"\n]\n")?;
+    }
+
+    output.finish()?;
+    info!("{} documents have been saved to {}", ctx.docs, ctx.output);
+
+    if let Some(path) = ctx.search_terms.as_ref() {
+        let queries: Vec<String> = (0..ctx.queries).map(|_| generator.query()).collect();
+        fs::write(path, serde_json::to_vec_pretty(&queries)?)?;
+        info!("{} queries have been saved to {}", queries.len(), path);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::Documents;
+
+    fn fields(specs: &str) -> Vec<FieldSpec> {
+        specs.split(',').map(|f| f.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn parses_counts() {
+        assert_eq!(parse_count("10M"), Ok(10_000_000));
+        assert_eq!(parse_count("1.5k"), Ok(1_500));
+        assert_eq!(parse_count("250"), Ok(250));
+        assert!(parse_count("1.5").is_err());
+        assert!(parse_count("1x").is_err());
+    }
+
+    #[test]
+    fn parses_field_specs() {
+        let parsed = fields("title:text(3..8 words),overview:text(40..120),genres:tags,rating:f64(1..5)");
+        assert_eq!(parsed[0].kind, FieldKind::Text { min: 3, max: 8 });
+        assert_eq!(parsed[1].kind, FieldKind::Text { min: 40, max: 120 });
+        assert_eq!(parsed[2].kind, FieldKind::Tags { min: 1, max: 3 });
+        assert_eq!(parsed[3].kind, FieldKind::F64 { min: 1.0, max: 5.0 });
+
+        assert!("title:text(8..3)".parse::<FieldSpec>().is_err());
+        assert!("id:text".parse::<FieldSpec>().is_err());
+        assert!("genres:tags(1..2 words)".parse::<FieldSpec>().is_err());
+        assert!("genres:tags(1..33)".parse::<FieldSpec>().is_err());
+        assert!("title:blob".parse::<FieldSpec>().is_err());
+        assert!("rating:f64(NaN..NaN)".parse::<FieldSpec>().is_err());
+        assert!("rating:f64(0..inf)".parse::<FieldSpec>().is_err());
+        assert!("rating:f64(-infinity..0)".parse::<FieldSpec>().is_err());
+    }
+
+    #[test]
+    fn picks_every_tag_from_a_steep_distribution() {
+        let tags = Vocabulary::new(TAG_VOCABULARY_SIZE, 50.0);
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let picked = tags.sample_distinct(&mut rng, TAG_VOCABULARY_SIZE);
+        assert_eq!(picked.len(), TAG_VOCABULARY_SIZE);
+        assert_eq!(picked.iter().collect::<BTreeSet<_>>().len(), TAG_VOCABULARY_SIZE);
+    }
+
+    #[tokio::test]
+    async fn generates_readable_documents() {
+
+        let dir = std::env::temp_dir().join(format!("lnxcli-synthetic-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("docs.jsonl.gz").to_str().unwrap().to_string();
+        let search_terms = dir.join("terms.json").to_str().unwrap().to_string();
+
+        let ctx = GenDataContext {
+            docs: 25,
+            fields: fields("title:text(3..8 words),overview:text(40..120),release_date:i64,genres:tags,rating:f64(1..5)"),
+            output: output.clone(),
+            search_terms: Some(search_terms.clone()),
+            queries: 10,
+            vocabulary: 1000,
+            zipf_exponent: 1.0,
+            seed: 7,
+        };
+        generate(ctx).unwrap();
+
+        let mut docs = Documents::open(&output, 10).unwrap();
+        let mut read = vec![];
+        while let Some(batch) = docs.next_batch().await.unwrap() {
+            read.extend(batch);
+        }
+
+        assert_eq!(read.len(), 25);
+        assert_eq!(read[24]["id"], "25");
+        for doc in read {
+            let words = doc["title"].as_str().unwrap().split(' ').count();
+            assert!((3..=8).contains(&words));
+            let words = doc["overview"].as_str().unwrap().split(' ').count();
+            assert!((40..=120).contains(&words));
+            assert!(doc["release_date"].is_i64());
+            assert!((1..=3).contains(&doc["genres"].as_array().unwrap().len()));
+            assert!((1.0..=5.0).contains(&doc["rating"].as_f64().unwrap()));
+        }
+
+        let queries: Vec<String> =
+            serde_json::from_slice(&std::fs::read(&search_terms).unwrap()).unwrap();
+        assert_eq!(queries.len(), 10);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
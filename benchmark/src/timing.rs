@@ -0,0 +1,247 @@
+use std::convert::TryFrom;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use hyper::client::connect::{Connected, Connection};
+use hyper::service::Service;
+use hyper::{Body, Client, Uri};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::rustls::{self, OwnedTrustAnchor, RootCertStore, ServerName};
+use tokio_rustls::TlsConnector;
+
+/// The time spent in each phase of a single request.
+///
+/// The connection phases are only set if the request had to open a new
+/// connection rather than re-using a pooled one.
+#[derive(Debug, Default, Copy, Clone)]
+pub(crate) struct PhaseTimings {
+    pub(crate) dns: Option<Duration>,
+    pub(crate) connect: Option<Duration>,
+    pub(crate) tls: Option<Duration>,
+
+    /// The time from the connection being ready to receiving the
+    /// response headers.
+    pub(crate) ttfb: Duration,
+
+    /// The time taken to download the response body.
+    pub(crate) body: Duration,
+}
+
+/// The connection phases of a newly opened connection.
+#[derive(Debug, Default, Copy, Clone)]
+struct ConnectTimings {
+    dns: Duration,
+    connect: Duration,
+    tls: Option<Duration>,
+}
+
+/// A HTTP client which records the time spent in each phase of a request.
+///
+/// Each worker has its own client and sends one request at a time, so the
+/// connection opened while sending a request belongs to that request.
+#[derive(Clone)]
+pub(crate) struct InstrumentedClient {
+    client: Client<TimingConnector, Body>,
+    last_connect: Arc<Mutex<Option<ConnectTimings>>>,
+}
+
+impl InstrumentedClient {
+    pub(crate) fn new(new_connection_per_request: bool) -> Self {
+        let last_connect = Arc::new(Mutex::new(None));
+        let connector = TimingConnector {
+            tls: TlsConnector::from(Arc::new(tls_config())),
+            last_connect: last_connect.clone(),
+        };
+
+        let mut builder = Client::builder();
+        if new_connection_per_request {
+            builder.pool_max_idle_per_host(0);
+        }
+
+        Self {
+            client: builder.build(connector),
+            last_connect,
+        }
+    }
+
+    /// Sends the request returning the status, body and phase timings.
+    pub(crate) async fn send(
+        &self,
+        request: reqwest::Request,
+    ) -> anyhow::Result<(u16, hyper::body::Bytes, PhaseTimings)> {
+        let mut builder = hyper::Request::builder()
+            .method(request.method().clone())
+            .uri(request.url().as_str());
+
+        for (key, value) in request.headers() {
+            builder = builder.header(key, value);
+        }
+
+        let body = request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .map(|body| body.to_vec())
+            .unwrap_or_default();
+        let request = builder.body(Body::from(body))?;
+
+        self.last_connect.lock().unwrap().take();
+
+        let start = Instant::now();
+        let response = self.client.request(request).await?;
+        let headers_received = start.elapsed();
+
+        let status = response.status().as_u16();
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let body_time = start.elapsed() - headers_received;
+
+        let connect = self.last_connect.lock().unwrap().take();
+        let setup = connect
+            .map(|c| c.dns + c.connect + c.tls.unwrap_or_default())
+            .unwrap_or_default();
+
+        let timings = PhaseTimings {
+            dns: connect.map(|c| c.dns),
+            connect: connect.map(|c| c.connect),
+            tls: connect.and_then(|c| c.tls),
+            ttfb: headers_received.saturating_sub(setup),
+            body: body_time,
+        };
+
+        Ok((status, body, timings))
+    }
+}
+
+fn tls_config() -> rustls::ClientConfig {
+    let mut roots = RootCertStore::empty();
+    roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+        OwnedTrustAnchor::from_subject_spki_name_constraints(
+            ta.subject,
+            ta.spki,
+            ta.name_constraints,
+        )
+    }));
+
+    rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_no_client_auth()
+}
+
+/// Opens connections while timing the DNS lookup, TCP connect and
+/// TLS handshake.
+#[derive(Clone)]
+struct TimingConnector {
+    tls: TlsConnector,
+    last_connect: Arc<Mutex<Option<ConnectTimings>>>,
+}
+
+impl TimingConnector {
+    async fn connect(self, uri: Uri) -> anyhow::Result<TimedStream> {
+        let host = uri
+            .host()
+            .ok_or_else(|| anyhow!("uri {} is missing a host", uri))?
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string();
+        let is_tls = uri.scheme_str() == Some("https");
+        let port = uri.port_u16().unwrap_or(if is_tls { 443 } else { 80 });
+
+        let start = Instant::now();
+        let addr = tokio::net::lookup_host((host.as_str(), port))
+            .await?
+            .next()
+            .ok_or_else(|| anyhow!("unable to resolve host {}", host))?;
+        let dns = start.elapsed();
+
+        let start = Instant::now();
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        let connect = start.elapsed();
+
+        let (stream, tls) = if is_tls {
+            let name = ServerName::try_from(host.as_str())?;
+
+            let start = Instant::now();
+            let stream = self.tls.connect(name, stream).await?;
+            (TimedStream::Tls(Box::new(stream)), Some(start.elapsed()))
+        } else {
+            (TimedStream::Plain(stream), None)
+        };
+
+        *self.last_connect.lock().unwrap() = Some(ConnectTimings { dns, connect, tls });
+
+        Ok(stream)
+    }
+}
+
+impl Service<Uri> for TimingConnector {
+    type Response = TimedStream;
+    type Error = anyhow::Error;
+    type Future = Pin<Box<dyn Future<Output = anyhow::Result<TimedStream>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        Box::pin(self.clone().connect(uri))
+    }
+}
+
+enum TimedStream {
+    Plain(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl Connection for TimedStream {
+    fn connected(&self) -> Connected {
+        Connected::new()
+    }
+}
+
+impl AsyncRead for TimedStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            Self::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for TimedStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            Self::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            Self::Tls(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            Self::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
@@ -2,66 +2,293 @@ use std::str::FromStr;
 use std::sync::Arc;
 use anyhow::anyhow;
 
-use reqwest::header::HeaderValue;
+use reqwest::header::{HeaderValue, CONTENT_TYPE};
 use reqwest::{StatusCode, Url};
 use serde::{Serialize, Deserialize};
 use serde_json::Value;
 use tokio::time::Instant;
 
+use crate::dataset::Documents;
 use crate::sampler::SamplerHandle;
-use crate::shared::{Query, RequestClient, TargetUri};
+use crate::schema::{self, FieldKind, IndexSchema};
+use crate::shared::{
+    expect_array,
+    expect_duration,
+    ClientOptions,
+    ParsedResponse,
+    Query,
+    RequestClient,
+    SearchResult,
+    TargetUri,
+};
 
 
-#[derive(Deserialize, Serialize)]
-struct Doc {
-    id: String,
-    poster: String,
-    release_date: i64,
-    title: String,
-    overview: String,
+/// The result of importing a single document.
+#[derive(Deserialize)]
+struct ImportResult {
+    success: bool,
 
     #[serde(default)]
-    genres: Vec<String>,
+    error: Option<String>,
 }
 
-
-pub(crate) async fn prep(address: &str, data: Value, index: &str) -> anyhow::Result<()> {
+/// Imports the documents into the collection in batches of JSONL.
+///
+/// TypeSense only accepts string ids so any numeric ids are converted
+/// to strings first.
+pub(crate) async fn prep(address: &str, documents: &mut Documents, index: &str) -> anyhow::Result<()> {
     let client = reqwest::Client::new();
 
     let start = Instant::now();
-    let docs: Vec<Doc> = serde_json::from_value(data)?;
-    for doc in docs {
+    while let Some(batch) = documents.next_batch().await? {
+        let batch_len = batch.len();
+        let mut body = String::new();
+        for mut doc in batch {
+            if let Some(id) = doc.get_mut("id").filter(|id| id.is_number()) {
+                *id = Value::from(id.to_string());
+            }
+
+            body.push_str(&serde_json::to_string(&doc)?);
+            body.push('\n');
+        }
+
         let r = client
-            .post(format!("{}/collections/{}/documents?action=upsert", address, index))
+            .post(format!("{}/collections/{}/documents/import?action=upsert", address, index))
             .header("X-TYPESENSE-API-KEY", HeaderValue::from_static("bench-key"))
-            .json(&doc)
+            .header(CONTENT_TYPE, HeaderValue::from_static("text/plain"))
+            .body(body)
             .send()
             .await?;
 
-        if r.status() != StatusCode::CREATED {
+        if r.status() != StatusCode::OK {
             return Err(anyhow!("got unexpected response code {} data: {}", r.status(), r.text().await?))
         }
+
+        // Each document has its own result so a failed document doesn't
+        // fail the request.
+        let results = r.text().await?;
+        let mut failed = 0;
+        let mut first_error = None;
+        for line in results.lines().filter(|line| !line.trim().is_empty()) {
+            let result: ImportResult = serde_json::from_str(line)?;
+            if !result.success {
+                failed += 1;
+                first_error = first_error.or(result.error);
+            }
+        }
+
+        if failed > 0 {
+            return Err(anyhow!(
+                "TypeSense failed to import {} of {} documents, first error: {}",
+                failed,
+                batch_len,
+                first_error.unwrap_or_default(),
+            ))
+        }
     }
 
     info!(
-        "TypeSense took {:?} to process submitted documents", start.elapsed()
+        "TypeSense took {:?} to process {} submitted documents", start.elapsed(), documents.read()
     );
 
     Ok(())
 }
 
+/// Infers the collection fields from the documents, the `id` field is
+/// left out as TypeSense always has one.
+pub(crate) fn infer_fields(docs: &[Value]) -> anyhow::Result<Vec<Value>> {
+    let mut fields = vec![];
+    for (name, field) in schema::infer_fields(docs)? {
+        if name == "id" {
+            continue;
+        }
+
+        let kind = match field.kind {
+            FieldKind::Text => "string",
+            FieldKind::Integer => "int64",
+            FieldKind::Float => "float",
+            FieldKind::Bool => "bool",
+            FieldKind::Empty => continue,
+            FieldKind::Unsupported => {
+                warn!("the field {:?} can't be inferred and is left out of the schema", name);
+                continue;
+            },
+        };
+
+        fields.push(collection_field(&name, kind, field.array));
+    }
+
+    if fields.is_empty() {
+        return Err(anyhow!("no fields could be inferred from the documents"));
+    }
+
+    info!("inferred collection fields: {}", Value::from(fields.clone()));
+
+    Ok(fields)
+}
+
+/// Maps the fields of an index schema to the collection fields.
+///
+/// The index schema doesn't say which fields hold arrays so this is taken
+/// from a sample of the documents.
+pub(crate) fn schema_fields(schema: &IndexSchema, docs: &[Value]) -> anyhow::Result<Vec<Value>> {
+    let inferred = schema::infer_fields(docs)?;
+
+    let mut fields = vec![];
+    for (name, field) in schema.fields.iter() {
+        if name == "id" {
+            continue;
+        }
+
+        let kind = match field.get("type").and_then(Value::as_str) {
+            Some("text") | Some("string") => "string",
+            Some("i64") | Some("u64") | Some("date") => "int64",
+            Some("f64") => "float",
+            other => {
+                return Err(anyhow!(
+                    "the field {:?} has the type {:?} which has no TypeSense equivalent",
+                    name,
+                    other.unwrap_or_default(),
+                ))
+            },
+        };
+
+        let array = inferred.get(name).map(|f| f.array).unwrap_or_default();
+        fields.push(collection_field(name, kind, array));
+    }
+
+    if fields.is_empty() {
+        return Err(anyhow!("the index schema has no fields other than the id"));
+    }
+
+    Ok(fields)
+}
+
+/// A collection field, every field is optional as the documents may not
+/// all have the same fields.
+fn collection_field(name: &str, kind: &str, array: bool) -> Value {
+    let kind = if array { format!("{}[]", kind) } else { kind.to_string() };
+    serde_json::json!({ "name": name, "type": kind, "optional": true })
+}
+
+/// Gets the number of documents in the collection.
+pub(crate) async fn count(address: &str, index: &str) -> anyhow::Result<usize> {
+    let collection = get_collection(address, index).await?;
+    collection
+        .get("num_documents")
+        .and_then(Value::as_u64)
+        .map(|count| count as usize)
+        .ok_or_else(|| anyhow!("missing \"num_documents\" number in the collection"))
+}
+
+async fn get_collection(address: &str, index: &str) -> anyhow::Result<Value> {
+    let r = reqwest::Client::new()
+        .get(format!("{}/collections/{}", address, index))
+        .header("X-TYPESENSE-API-KEY", HeaderValue::from_static("bench-key"))
+        .send()
+        .await?;
+
+    if r.status() != StatusCode::OK {
+        return Err(anyhow!("got unexpected response code {} data: {}", r.status(), r.text().await?))
+    }
+
+    Ok(r.json().await?)
+}
+
+/// Gets the string fields of the collection to search.
+pub async fn query_by(address: &str, index: &str) -> anyhow::Result<String> {
+    let collection = get_collection(address, index).await?;
+    let fields: Vec<&str> = collection
+        .get("fields")
+        .and_then(Value::as_array)
+        .map(|fields| {
+            fields
+                .iter()
+                .filter(|field| {
+                    matches!(
+                        field.get("type").and_then(Value::as_str),
+                        Some("string") | Some("string[]")
+                    )
+                })
+                .filter_map(|field| field.get("name").and_then(Value::as_str))
+                .filter(|name| *name != "id")
+                .collect()
+        })
+        .unwrap_or_default();
+
+    if fields.is_empty() {
+        return Err(anyhow!(
+            "the TypeSense collection {:?} has no string fields to search",
+            index
+        ));
+    }
+
+    Ok(fields.join(","))
+}
+
+/// Creates the collection with the given schema, replacing any existing
+/// collection with the same name.
+pub(crate) async fn create_collection(address: &str, index: &str, schema: &Value) -> anyhow::Result<()> {
+    let mut schema = schema
+        .as_object()
+        .cloned()
+        .ok_or_else(|| anyhow!("the TypeSense collection schema must be an object"))?;
+    schema.insert("name".to_string(), Value::from(index));
+
+    let client = reqwest::Client::new();
+    let _ = client
+        .delete(format!("{}/collections/{}", address, index))
+        .header("X-TYPESENSE-API-KEY", HeaderValue::from_static("bench-key"))
+        .send()
+        .await?;
+
+    let r = client
+        .post(format!("{}/collections", address))
+        .header("X-TYPESENSE-API-KEY", HeaderValue::from_static("bench-key"))
+        .json(&schema)
+        .send()
+        .await?;
+
+    if r.status() != StatusCode::CREATED {
+        return Err(anyhow!("got unexpected response code {} data: {}", r.status(), r.text().await?))
+    }
+
+    info!("created TypeSense collection {:?}", index);
+
+    Ok(())
+}
+
+pub(crate) async fn delete_collection(address: &str, index: &str) -> anyhow::Result<()> {
+    let r = reqwest::Client::new()
+        .delete(format!("{}/collections/{}", address, index))
+        .header("X-TYPESENSE-API-KEY", HeaderValue::from_static("bench-key"))
+        .send()
+        .await?;
+
+    if r.status() != StatusCode::OK {
+        return Err(anyhow!("got unexpected response code {} data: {}", r.status(), r.text().await?))
+    }
+
+    info!("deleted TypeSense collection {:?}", index);
+
+    Ok(())
+}
+
 pub(crate) async fn bench_standard(
     address: Arc<String>,
     sample: SamplerHandle,
     terms: Vec<String>,
     index: String,
+    options: ClientOptions,
+    query_by: Arc<String>,
 ) -> anyhow::Result<()> {
     crate::shared::start_standard(
         address,
         sample,
         terms,
         &index,
-        move |client, uri, query| async { search(client, uri, query).await },
+        options,
+        move |client, uri, query| search(client, uri, query_by.clone(), query),
     )
     .await
 }
@@ -71,36 +298,93 @@ pub(crate) async fn bench_typing(
     sample: SamplerHandle,
     terms: Vec<String>,
     index: String,
+    options: ClientOptions,
+    query_by: Arc<String>,
 ) -> anyhow::Result<()> {
     crate::shared::start_typing(
         address,
         sample,
         terms,
         &index,
-        move |client, uri, query| async { search(client, uri, query).await },
+        options,
+        move |client, uri, query| search(client, uri, query_by.clone(), query),
     )
     .await
 }
 
 #[derive(Serialize)]
-struct QueryPayload {
+struct QueryPayload<'a> {
     q: String,
-    query_by: &'static str,
+    query_by: &'a str,
 }
 
-async fn search(client: RequestClient, uri: TargetUri, query: Query) -> anyhow::Result<u16> {
+async fn search(
+    client: RequestClient,
+    uri: TargetUri,
+    query_by: Arc<String>,
+    query: Query,
+) -> anyhow::Result<SearchResult> {
     let uri = uri.replace("indexes", "collections")
         .replace("/search", "/documents/search");
 
     let ref_uri = Url::from_str(&uri)?;
 
-    let r = client
+    let request = client
         .get(ref_uri)
         .header("X-TYPESENSE-API-KEY", HeaderValue::from_static("bench-key"))
-        .query(&QueryPayload { q: query, query_by: "title,overview" })
-        .send()
-        .await?;
+        .query(&QueryPayload { q: query, query_by: &query_by });
+
+    client.send_search(request, &[200], parse).await
+}
 
-    let status = r.status().clone();
-    Ok(status.as_u16())
+fn parse(data: &Value) -> Result<ParsedResponse, String> {
+    let hits = expect_array(data, "/hits")?;
+    if !hits.iter().all(|hit| hit.get("document").map(Value::is_object).unwrap_or(false)) {
+        return Err("hit is missing the \"document\" object".to_string());
+    }
+
+    if !data.get("found").map(Value::is_number).unwrap_or(false) {
+        return Err("missing \"found\" number".to_string());
+    }
+
+    let server_time = expect_duration(data, "/search_time_ms", 0.001)?;
+
+    Ok(ParsedResponse {
+        hits: hits.len(),
+        server_time: Some(server_time),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_index_schema_fields() {
+        let schema = IndexSchema::from_value(&serde_json::json!({
+            "fields": {
+                "id": { "type": "string", "stored": true },
+                "title": { "type": "text", "stored": true },
+                "genres": { "type": "text", "stored": true },
+                "rating": { "type": "f64", "stored": true, "fast": true },
+            },
+        }))
+        .unwrap();
+        let docs = vec![serde_json::json!({ "id": "1", "title": "Up", "genres": ["Animation"], "rating": 8.3 })];
+
+        assert_eq!(
+            Value::from(schema_fields(&schema, &docs).unwrap()),
+            serde_json::json!([
+                { "name": "genres", "type": "string[]", "optional": true },
+                { "name": "rating", "type": "float", "optional": true },
+                { "name": "title", "type": "string", "optional": true },
+            ])
+        );
+
+        let schema = IndexSchema::from_value(&serde_json::json!({
+            "fields": { "cover": { "type": "bytes" } },
+        }))
+        .unwrap();
+        assert!(schema_fields(&schema, &docs).is_err());
+    }
 }
@@ -2,10 +2,14 @@
 extern crate log;
 
 use std::net::SocketAddr;
+use std::time::Duration;
 
 use benchmark::{self, BenchMode, BenchTarget};
 use structopt::StructOpt;
+use test_accuracy::{QueryKind, Target, TargetSpec};
 
+// The commands are only parsed once so the size of the bench flags doesn't matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, StructOpt)]
 #[structopt(name = "lnxcli", about = "A utility cli for benchmarking and testing")]
 pub enum Commands {
@@ -15,25 +19,44 @@ pub enum Commands {
     /// This is very useful to do when adjusting your worker thread counts
     /// to test latency and throughput.
     Bench {
+        /// The path to a TOML file describing one or more named scenarios to
+        /// run in sequence.
+        ///
+        /// Each scenario takes the same settings as the flags, any flags given
+        /// override the values in the file.
+        #[structopt(long)]
+        scenario: Option<String>,
+
         /// The address of the server to benchmark.
         #[structopt(long, short = "a")]
-        address: String,
+        address: Option<String>,
 
-        /// The target platform to bench mark, either 'lnx' or 'meilisearch'.
+        /// The target platform to bench mark, one of 'lnx', 'meilisearch',
+        /// 'typesense' or 'generic'.
+        #[structopt(long)]
+        target: Option<BenchTarget>,
+
+        /// The path to the JSON file describing the requests sent by the
+        /// 'generic' target.
+        ///
+        /// This is required when the target is 'generic'.
         #[structopt(long)]
-        target: BenchTarget,
+        target_config: Option<String>,
 
         /// The target platform to bench mark, either 'typing' or 'standard'.
         #[structopt(long, short = "m")]
-        mode: BenchMode,
+        mode: Option<BenchMode>,
 
         /// The path to the target JSON file to load data for the benchmark.
+        ///
+        /// This can be a JSON array, JSON lines (`.jsonl` or `.ndjson`) or a CSV file
+        /// with a header row, optionally compressed with gzip (`.gz`) or zstd (`.zst`).
         #[structopt(long, short = "data")]
-        data_file: String,
+        data_file: Option<String>,
 
         /// The amount of concurrent searches to run at any one time.
         #[structopt(long, short = "c")]
-        concurrency: usize,
+        concurrency: Option<usize>,
 
         /// The number of threads to run the test with.
         ///
@@ -41,21 +64,452 @@ pub enum Commands {
         #[structopt(long)]
         threads: Option<usize>,
 
+        /// The number of seconds to keep searching for, the search terms are
+        /// repeated until this has passed.
+        ///
+        /// If not set each client searches every term once.
+        #[structopt(long)]
+        duration: Option<u64>,
+
+        /// The number of documents uploaded in each request, defaults to 10000.
+        ///
+        /// The data file is streamed so only a few batches are held in memory.
+        #[structopt(long)]
+        batch_size: Option<usize>,
+
+        /// The number of batches uploaded to lnx at once, defaults to 4.
+        ///
+        /// Batches which fail with a server error are retried.
+        #[structopt(long)]
+        upload_concurrency: Option<usize>,
+
+        /// The file to save the progress of the lnx upload to.
+        ///
+        /// The uploaded batches are committed and recorded every few batches, if the
+        /// upload is interrupted the next run with the same data file, index and
+        /// batch size carries on from the checkpoint rather than clearing the index.
+        /// The file is removed once the upload has finished.
+        #[structopt(long)]
+        upload_checkpoint: Option<String>,
+
         /// The directory to output the image results.
         #[structopt(long, short = "o")]
-        output_dir: String,
+        output_dir: Option<String>,
 
         /// The path to get the query string data.
         #[structopt(long, short = "terms")]
-        search_terms: String,
+        search_terms: Option<String>,
 
         /// Whether or not use the existing data in the system or flush it.
         #[structopt(long)]
         no_prep: bool,
 
+        /// Don't check the index holds as many documents as the data file once
+        /// they have been uploaded, e.g. if the dataset has duplicate ids.
+        #[structopt(long)]
+        no_verify: bool,
+
+        /// The index name to target, defaults to 'bench'.
+        #[structopt(long, short)]
+        index: Option<String>,
+
+        /// The path to a JSON file of index settings applied before the
+        /// documents are uploaded.
+        ///
+        /// lnx recreates the index from these settings, MeiliSearch updates the
+        /// index settings and TypeSense recreates the collection from them as
+        /// its schema.
+        #[structopt(long)]
+        index_settings: Option<String>,
+
+        /// The path to a JSON file of the lnx index schema, an object of the
+        /// `fields` and optionally the `search_fields`.
+        ///
+        /// The index is created from the schema before the documents are
+        /// uploaded, replacing any existing index, and deleted once the
+        /// benchmark has finished.
+        #[structopt(long)]
+        index_schema: Option<String>,
+
+        /// Infer the lnx index schema from the documents rather than reading
+        /// it from `--index-schema`.
+        ///
+        /// TypeSense collections are recreated with an inferred schema rather
+        /// than the one given by `--index-settings`.
+        #[structopt(long)]
+        infer_schema: bool,
+
+        /// Keep the index created from the schema or settings once the
+        /// benchmark has finished rather than deleting it.
+        #[structopt(long)]
+        keep_index: bool,
+
+        /// The comma separated fields TypeSense searches, e.g. 'title,overview'.
+        ///
+        /// If not set every string field of the collection is searched.
+        #[structopt(long, use_delimiter = true)]
+        query_by: Option<Vec<String>>,
+
+        /// The MeiliSearch master or API key, this is sent as a bearer token
+        /// with the prep and search requests.
+        #[structopt(long)]
+        api_key: Option<String>,
+
+        /// Parse and validate each search response body.
+        ///
+        /// This records the hit counts, the zero result rate and any
+        /// responses which don't match the target's expected schema. The
+        /// processing time reported by the server is recorded next to the
+        /// client latency to chart the transport and serialization overhead.
+        #[structopt(long)]
+        parse_responses: bool,
+
+        /// Record the time spent resolving DNS, connecting, negotiating TLS,
+        /// waiting for the first byte and downloading the body of each search.
+        #[structopt(long)]
+        phase_timing: bool,
+
+        /// Open a new connection for every search rather than re-using
+        /// kept alive connections, this measures the cold connection cost.
+        #[structopt(long)]
+        new_connection_per_request: bool,
+
+        /// Keep every latency sample rather than only the histograms and
+        /// write them to `raw-latencies.json` in the output directory.
+        #[structopt(long)]
+        keep_raw_samples: bool,
+
+        /// Stream a CSV record of every request to the given file.
+        ///
+        /// Each record has the time the request was sent, the worker id, the query,
+        /// the prefix length when typing, the status, the latency in microseconds
+        /// and the number of bytes received.
+        #[structopt(long)]
+        raw_log: Option<String>,
+
+        /// The address to serve live benchmark metrics on in the Prometheus
+        /// text format, e.g. `127.0.0.1:9100`.
+        ///
+        /// This exposes the request counts by status, a latency histogram and the
+        /// number of active workers on `/metrics` while the benchmark is running.
+        #[structopt(long)]
+        metrics_bind: Option<SocketAddr>,
+
+        /// The pid of a local server to sample the CPU, memory, threads, open
+        /// files and disk IO of from `/proc` during the benchmark.
+        #[structopt(long)]
+        server_pid: Option<u32>,
+
+        /// The name of a local server process to sample, this is used to
+        /// look up the pid if `--server-pid` isn't given.
+        #[structopt(long)]
+        server_process: Option<String>,
+
+        /// The comma separated concurrency values to sweep over.
+        ///
+        /// A benchmark is run for every combination of the swept concurrency,
+        /// threads and modes, then the results are compared in a matrix and a
+        /// heatmap of the p99 latency and throughput.
+        #[structopt(long, use_delimiter = true)]
+        sweep_concurrency: Option<Vec<usize>>,
+
+        /// The comma separated thread counts to sweep over.
+        #[structopt(long, use_delimiter = true)]
+        sweep_threads: Option<Vec<usize>>,
+
+        /// The comma separated modes to sweep over, e.g. 'standard,typing'.
+        #[structopt(long, use_delimiter = true)]
+        sweep_mode: Option<Vec<BenchMode>>,
+
+        /// The path to a JSON object of lnx index settings to sweep over, each
+        /// setting has a list of values to try, e.g. `{"reader_threads": [1, 2, 4]}`.
+        ///
+        /// The index is recreated from the `--index-settings` and `--index-schema`
+        /// with each combination of the swept values applied and the documents are
+        /// re-ingested before the workload is run. The settings giving the best trade-off between the p99
+        /// latency and throughput are reported.
+        #[structopt(long)]
+        sweep_index_settings: Option<String>,
+    },
+
+    /// Evaluates the relevance of a target's search results against a set
+    /// of judged queries.
+    ///
+    /// This reports the precision@k, recall@k, MRR and nDCG@k of each query
+    /// along with the mean across all queries.
+    Accuracy {
+        /// The address of the server to evaluate.
+        #[structopt(long, short = "a")]
+        address: String,
+
+        /// The target platform to evaluate, one of 'lnx', 'meilisearch' or
+        /// 'typesense'.
+        #[structopt(long)]
+        target: Target,
+
+        /// The path to the JSON judgments file.
+        ///
+        /// This is a list of `{"query": "...", "relevant": ...}` objects where
+        /// `relevant` is either a list of document ids or a map of document
+        /// ids to their relevance grade.
+        #[structopt(long, short = "j")]
+        judgments: String,
+
+        /// The number of top results to evaluate.
+        #[structopt(long, short = "k", default_value = "10")]
+        k: usize,
+
+        /// The document field containing the ids used by the judgments.
+        #[structopt(long, default_value = "id")]
+        id_field: String,
+
+        /// The kind of query to run, either 'fuzzy' or 'normal'.
+        #[structopt(long, default_value = "fuzzy")]
+        kind: QueryKind,
+
         /// The index name to target.
         #[structopt(long, short, default_value = "bench")]
         index: String,
+
+        /// The path to write the JSON report to.
+        #[structopt(long, short = "o")]
+        output: Option<String>,
+    },
+
+    /// Measures how robust a target is to typos by searching generated
+    /// misspellings of the dataset's titles.
+    ///
+    /// Each sampled title is misspelled with swapped, dropped and doubled
+    /// letters at every edit distance up to the max distance, the hit rate is
+    /// the percentage of searches which return the source document in the
+    /// top k results.
+    TypoTolerance {
+        /// The address of the server to evaluate.
+        #[structopt(long, short = "a")]
+        address: String,
+
+        /// The target platform to evaluate, one of 'lnx', 'meilisearch' or
+        /// 'typesense'.
+        #[structopt(long)]
+        target: Target,
+
+        /// The path to the JSON dataset the titles are taken from.
+        #[structopt(long, short = "data")]
+        data_file: String,
+
+        /// The document field containing the document ids.
+        #[structopt(long, default_value = "id")]
+        id_field: String,
+
+        /// The document field containing the titles to misspell.
+        #[structopt(long, default_value = "title")]
+        title_field: String,
+
+        /// The number of top results the source document must appear in.
+        #[structopt(long, short = "k", default_value = "10")]
+        k: usize,
+
+        /// The kinds of query to run, separated by commas.
+        #[structopt(long, default_value = "fuzzy,normal", use_delimiter = true)]
+        kinds: Vec<QueryKind>,
+
+        /// The number of titles to sample from the dataset.
+        #[structopt(long, default_value = "200")]
+        samples: usize,
+
+        /// The maximum edit distance of the generated misspellings.
+        #[structopt(long, default_value = "3")]
+        max_distance: usize,
+
+        /// The seed used to sample titles and generate misspellings.
+        #[structopt(long, default_value = "0")]
+        seed: u64,
+
+        /// The index name to target.
+        #[structopt(long, short, default_value = "bench")]
+        index: String,
+
+        /// The path to write the JSON report to.
+        #[structopt(long, short = "o")]
+        output: Option<String>,
+    },
+
+    /// Measures how many characters must be typed before a document appears
+    /// in the results when searching as you type.
+    ///
+    /// Each sampled title is typed out a character at a time in the same way
+    /// as the 'typing' bench mode, recording the first prefix length which
+    /// returns the document in the top k results. Several targets can be
+    /// compared, e.g. two lnx indexes created with and without
+    /// `use_fast_fuzzy` and labelled as such, see the README.
+    PrefixAccuracy {
+        /// The targets to evaluate written as `[<label>=]<target>:<index>@<address>`,
+        /// e.g. `lnx:movies@http://127.0.0.1:8000` or
+        /// `fast-fuzzy=lnx:movies_fast@http://127.0.0.1:8000`.
+        #[structopt(long = "target", required = true)]
+        targets: Vec<TargetSpec>,
+
+        /// The path to the JSON dataset the titles are taken from.
+        #[structopt(long, short = "data")]
+        data_file: String,
+
+        /// The document field containing the document ids.
+        #[structopt(long, default_value = "id")]
+        id_field: String,
+
+        /// The document field containing the titles to type out.
+        #[structopt(long, default_value = "title")]
+        title_field: String,
+
+        /// The number of top results the document must appear in.
+        #[structopt(long, short = "k", default_value = "10")]
+        k: usize,
+
+        /// The kind of query to run, either 'fuzzy' or 'normal'.
+        #[structopt(long, default_value = "fuzzy")]
+        kind: QueryKind,
+
+        /// The number of titles to sample from the dataset.
+        #[structopt(long, default_value = "200")]
+        samples: usize,
+
+        /// The seed used to sample titles.
+        #[structopt(long, default_value = "0")]
+        seed: u64,
+
+        /// The directory to output the chart and JSON report.
+        #[structopt(long, short = "o")]
+        output_dir: String,
+    },
+
+    /// Compares the top results several targets return for the same queries.
+    ///
+    /// This reports the Jaccard overlap and rank-biased overlap (RBO) between
+    /// each pair of targets per query and overall, along with the queries
+    /// where the targets disagree the most.
+    Overlap {
+        /// The targets to compare written as `[<label>=]<target>:<index>@<address>`,
+        /// e.g. `lnx:movies@http://127.0.0.1:8000` or
+        /// `fast-fuzzy=lnx:movies_fast@http://127.0.0.1:8000`.
+        #[structopt(long = "target", required = true, min_values = 2)]
+        targets: Vec<TargetSpec>,
+
+        /// The path to get the query string data.
+        #[structopt(long, short = "terms")]
+        search_terms: String,
+
+        /// The document field containing the document ids.
+        #[structopt(long, default_value = "id")]
+        id_field: String,
+
+        /// The number of top results to compare.
+        #[structopt(long, short = "k", default_value = "10")]
+        k: usize,
+
+        /// The kind of query to run, either 'fuzzy' or 'normal'.
+        #[structopt(long, default_value = "fuzzy")]
+        kind: QueryKind,
+
+        /// The RBO persistence between 0 and 1 exclusive, lower values weight the
+        /// top results more heavily.
+        #[structopt(long, default_value = "0.9")]
+        persistence: f64,
+
+        /// The number of most divergent queries to list.
+        #[structopt(long, default_value = "10")]
+        divergent: usize,
+
+        /// The path to write the JSON report to.
+        #[structopt(long, short = "o")]
+        output: Option<String>,
+    },
+
+    /// Regenerates the stats and charts of a previous benchmark run from
+    /// its saved run data.
+    ///
+    /// This can re-draw the charts at a different size or with a different
+    /// set of percentiles, and a raw request log can be filtered to a time window.
+    Report {
+        /// The saved run to report on, either the `run-data.json` written to the
+        /// benchmark output directory or a raw request log CSV.
+        #[structopt(long, short = "i")]
+        input: String,
+
+        /// The directory to write the charts to.
+        #[structopt(long, short = "o")]
+        output_dir: String,
+
+        /// The width of the charts in pixels.
+        #[structopt(long, default_value = "1920")]
+        width: u32,
+
+        /// The height of the charts in pixels.
+        #[structopt(long, default_value = "1080")]
+        height: u32,
+
+        /// The comma separated percentiles to log and chart.
+        #[structopt(long, use_delimiter = true, default_value = "50,75,90,95,99,99.9")]
+        percentiles: Vec<f64>,
+
+        /// Only include requests sent at least this many seconds after the
+        /// first request, this requires a raw request log.
+        #[structopt(long, parse(try_from_str = parse_seconds))]
+        since: Option<Duration>,
+
+        /// Only include requests sent up to this many seconds after the
+        /// first request, this requires a raw request log.
+        #[structopt(long, parse(try_from_str = parse_seconds))]
+        until: Option<Duration>,
+    },
+
+    /// Generates a synthetic dataset and a matching set of queries.
+    ///
+    /// The text is made of words drawn from a Zipf distributed vocabulary so
+    /// the datasets behave like natural language at any size.
+    GenData {
+        /// The number of documents to generate, e.g. 5000, 250k or 10M.
+        #[structopt(long, parse(try_from_str = benchmark::parse_count))]
+        docs: u64,
+
+        /// The comma separated fields of each document as `name:kind` with
+        /// an optional inclusive range, an `id` field is always added.
+        ///
+        /// The kinds are 'text(min..max words)', 'tags(min..max tags)',
+        /// 'i64(min..max)' and 'f64(min..max)'.
+        #[structopt(
+            long,
+            use_delimiter = true,
+            default_value = "title:text(3..8 words),overview:text(40..120),release_date:i64,genres:tags"
+        )]
+        fields: Vec<benchmark::FieldSpec>,
+
+        /// The data file to write the documents to.
+        ///
+        /// This can be a JSON array (`.json`) or JSON lines (`.jsonl` or `.ndjson`),
+        /// optionally compressed with gzip (`.gz`) or zstd (`.zst`).
+        #[structopt(long, short = "o")]
+        output: String,
+
+        /// The path to write the generated queries to as a JSON array.
+        #[structopt(long, short = "t")]
+        search_terms: Option<String>,
+
+        /// The number of queries to generate.
+        #[structopt(long, default_value = "1000")]
+        queries: usize,
+
+        /// The number of distinct words in the vocabulary.
+        #[structopt(long, default_value = "50000")]
+        vocabulary: usize,
+
+        /// The exponent of the Zipf distribution the words are drawn from.
+        #[structopt(long, default_value = "1.0")]
+        zipf_exponent: f64,
+
+        /// The random seed, the same seed and flags always generate the
+        /// same dataset.
+        #[structopt(long, default_value = "0")]
+        seed: u64,
     },
 
     /// Runs a demo app to play around with the search as you type setup.
@@ -89,40 +543,274 @@ pub enum Commands {
     },
 }
 
+/// Parses a number of seconds which can't be negative, e.g. `1.5`.
+fn parse_seconds(s: &str) -> Result<Duration, String> {
+    s.parse::<f64>()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| format!("invalid number of seconds {:?}, expected a number of 0 or more", s))
+}
+
 fn main() -> anyhow::Result<()> {
-    let _ = std::env::set_var("RUST_LOG", "info");
+    std::env::set_var("RUST_LOG", "info");
     pretty_env_logger::init();
 
     let cmd: Commands = Commands::from_args();
 
     match cmd {
         Commands::Bench {
+            scenario,
             address,
             target,
+            target_config,
             mode,
             data_file,
             concurrency,
             threads,
+            duration,
+            batch_size,
+            upload_concurrency,
+            upload_checkpoint,
             output_dir,
             search_terms,
             no_prep,
+            no_verify,
             index,
+            index_settings,
+            index_schema,
+            infer_schema,
+            keep_index,
+            query_by,
+            api_key,
+            parse_responses,
+            phase_timing,
+            new_connection_per_request,
+            keep_raw_samples,
+            raw_log,
+            metrics_bind,
+            server_pid,
+            server_process,
+            sweep_concurrency,
+            sweep_threads,
+            sweep_mode,
+            sweep_index_settings,
         } => {
-            let ctx = benchmark::Context {
+            let index_settings = match index_settings {
+                Some(path) => Some(serde_json::from_str(&std::fs::read_to_string(path)?)?),
+                None => None,
+            };
+            let index_schema = match index_schema {
+                Some(path) => Some(serde_json::from_str(&std::fs::read_to_string(path)?)?),
+                None => None,
+            };
+            let sweep_index_settings = match sweep_index_settings {
+                Some(path) => Some(serde_json::from_str(&std::fs::read_to_string(path)?)?),
+                None => None,
+            };
+
+            // The flags can only turn the switches on so an unset flag
+            // leaves the scenario's value alone.
+            let flag = |set: bool| if set { Some(true) } else { None };
+            let options = benchmark::BenchOptions {
                 address,
-                data_file,
-                concurrency,
                 target,
+                target_config,
                 mode,
+                data_file,
                 search_terms,
-                no_prep,
-                threads: threads.unwrap_or_else(|| num_cpus::get()),
-                output: output_dir,
+                concurrency,
+                threads,
+                duration,
+                batch_size,
+                upload_concurrency,
+                upload_checkpoint,
+                output_dir,
                 index,
+                index_settings,
+                index_schema,
+                infer_schema: flag(infer_schema),
+                keep_index: flag(keep_index),
+                query_by,
+                api_key,
+                no_prep: flag(no_prep),
+                no_verify: flag(no_verify),
+                parse_responses: flag(parse_responses),
+                phase_timing: flag(phase_timing),
+                new_connection_per_request: flag(new_connection_per_request),
+                keep_raw_samples: flag(keep_raw_samples),
+                raw_log,
+                metrics_bind,
+                server_pid,
+                server_process,
+                sweep: benchmark::SweepOptions {
+                    concurrency: sweep_concurrency,
+                    threads: sweep_threads,
+                    mode: sweep_mode,
+                    index_settings: sweep_index_settings,
+                },
             };
 
             info!("starting benchmark system");
-            benchmark::run(ctx)
+            benchmark::run_scenarios(scenario.as_deref(), options)
+        },
+
+        Commands::Accuracy {
+            address,
+            target,
+            judgments,
+            k,
+            id_field,
+            kind,
+            index,
+            output,
+        } => {
+            let ctx = test_accuracy::Context {
+                address,
+                target,
+                index,
+                judgments,
+                k,
+                id_field,
+                kind,
+                output,
+            };
+
+            info!("starting accuracy evaluation");
+            test_accuracy::run(ctx)
+        },
+
+        Commands::TypoTolerance {
+            address,
+            target,
+            data_file,
+            id_field,
+            title_field,
+            k,
+            kinds,
+            samples,
+            max_distance,
+            seed,
+            index,
+            output,
+        } => {
+            let ctx = test_accuracy::TypoContext {
+                address,
+                target,
+                index,
+                data_file,
+                id_field,
+                title_field,
+                k,
+                kinds,
+                samples,
+                max_distance,
+                seed,
+                output,
+            };
+
+            info!("starting typo tolerance evaluation");
+            test_accuracy::run_typos(ctx)
+        },
+
+        Commands::PrefixAccuracy {
+            targets,
+            data_file,
+            id_field,
+            title_field,
+            k,
+            kind,
+            samples,
+            seed,
+            output_dir,
+        } => {
+            let ctx = test_accuracy::PrefixContext {
+                targets,
+                data_file,
+                id_field,
+                title_field,
+                k,
+                kind,
+                samples,
+                seed,
+                output_dir,
+            };
+
+            info!("starting prefix accuracy evaluation");
+            test_accuracy::run_prefix(ctx)
+        },
+
+        Commands::Overlap {
+            targets,
+            search_terms,
+            id_field,
+            k,
+            kind,
+            persistence,
+            divergent,
+            output,
+        } => {
+            let ctx = test_accuracy::OverlapContext {
+                targets,
+                search_terms,
+                id_field,
+                k,
+                kind,
+                persistence,
+                divergent,
+                output,
+            };
+
+            info!("starting result overlap comparison");
+            test_accuracy::run_overlap(ctx)
+        },
+
+        Commands::Report {
+            input,
+            output_dir,
+            width,
+            height,
+            percentiles,
+            since,
+            until,
+        } => {
+            let ctx = benchmark::ReportContext {
+                input,
+                output: output_dir,
+                chart_options: benchmark::ChartOptions {
+                    size: (width, height),
+                    percentiles,
+                },
+                since,
+                until,
+            };
+
+            info!("generating report");
+            benchmark::report(ctx)
+        },
+
+        Commands::GenData {
+            docs,
+            fields,
+            output,
+            search_terms,
+            queries,
+            vocabulary,
+            zipf_exponent,
+            seed,
+        } => {
+            let ctx = benchmark::GenDataContext {
+                docs,
+                fields,
+                output,
+                search_terms,
+                queries,
+                vocabulary,
+                zipf_exponent,
+                seed,
+            };
+
+            info!("generating synthetic dataset");
+            benchmark::gen_data(ctx)
         },
 
         Commands::Demo {
@@ -1,7 +1,159 @@
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn it_works() {
-        assert_eq!(2 + 2, 4);
+#[macro_use]
+extern crate log;
+
+mod overlap;
+mod prefix;
+mod relevance;
+mod targets;
+mod typos;
+
+use std::future::Future;
+
+use anyhow::anyhow;
+use serde_json::Value;
+use tokio::fs;
+
+pub use targets::{QueryKind, Target, TargetSpec};
+
+/// The context for evaluating relevance against a judgments file.
+pub struct Context {
+    pub address: String,
+    pub target: Target,
+    pub index: String,
+    pub judgments: String,
+    pub k: usize,
+    pub id_field: String,
+    pub kind: QueryKind,
+    pub output: Option<String>,
+}
+
+/// The context for evaluating typo tolerance with generated misspellings.
+pub struct TypoContext {
+    pub address: String,
+    pub target: Target,
+    pub index: String,
+    pub data_file: String,
+    pub id_field: String,
+    pub title_field: String,
+    pub k: usize,
+    pub kinds: Vec<QueryKind>,
+    pub samples: usize,
+    pub max_distance: usize,
+    pub seed: u64,
+    pub output: Option<String>,
+}
+
+/// The context for evaluating how many characters must be typed before
+/// a document appears in the results.
+pub struct PrefixContext {
+    pub targets: Vec<TargetSpec>,
+    pub data_file: String,
+    pub id_field: String,
+    pub title_field: String,
+    pub k: usize,
+    pub kind: QueryKind,
+    pub samples: usize,
+    pub seed: u64,
+    pub output_dir: String,
+}
+
+/// The context for comparing the top results of several targets.
+pub struct OverlapContext {
+    pub targets: Vec<TargetSpec>,
+    pub search_terms: String,
+    pub id_field: String,
+    pub k: usize,
+    pub kind: QueryKind,
+    pub persistence: f64,
+    pub divergent: usize,
+    pub output: Option<String>,
+}
+
+/// A document of the dataset reduced to its id and title.
+pub(crate) struct Document {
+    pub(crate) id: String,
+    pub(crate) title: String,
+}
+
+pub fn run(ctx: Context) -> anyhow::Result<()> {
+    block_on(relevance::evaluate(ctx))
+}
+
+pub fn run_typos(ctx: TypoContext) -> anyhow::Result<()> {
+    block_on(async move {
+        let documents = load_documents(&ctx.data_file, &ctx.id_field, &ctx.title_field).await?;
+        typos::evaluate(ctx, documents).await
+    })
+}
+
+pub fn run_prefix(ctx: PrefixContext) -> anyhow::Result<()> {
+    block_on(async move {
+        let documents = load_documents(&ctx.data_file, &ctx.id_field, &ctx.title_field).await?;
+        prefix::evaluate(ctx, documents).await
+    })
+}
+
+pub fn run_overlap(ctx: OverlapContext) -> anyhow::Result<()> {
+    block_on(async move {
+        let data = fs::read_to_string(&ctx.search_terms).await?;
+        let terms: Vec<String> = serde_json::from_str(&data)?;
+        overlap::evaluate(ctx, terms).await
+    })
+}
+
+fn block_on<T: Future<Output = anyhow::Result<()>>>(fut: T) -> anyhow::Result<()> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+
+    runtime.block_on(fut)
+}
+
+/// Loads the id and title of every document in the JSON dataset.
+pub(crate) async fn load_documents(
+    path: &str,
+    id_field: &str,
+    title_field: &str,
+) -> anyhow::Result<Vec<Document>> {
+    let data = fs::read_to_string(path).await?;
+    let docs: Vec<Value> = serde_json::from_str(&data)?;
+
+    let mut documents = Vec::with_capacity(docs.len());
+    for doc in docs {
+        let field = |name: &str| {
+            doc.get(name)
+                .and_then(value_to_string)
+                .ok_or_else(|| anyhow!("document is missing the {:?} field: {}", name, doc))
+        };
+
+        documents.push(Document {
+            id: field(id_field)?,
+            title: field(title_field)?,
+        });
+    }
+
+    Ok(documents)
+}
+
+/// Converts a field value into a string.
+///
+/// lnx returns every field as a list of values so the first value is used.
+pub(crate) fn value_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Array(values) => values.first().and_then(value_to_string),
+        _ => None,
+    }
+}
+
+/// Shortens the text to at most `length` characters for the result tables.
+pub(crate) fn truncate(text: &str, length: usize) -> String {
+    if text.chars().count() <= length {
+        return text.to_string();
     }
+
+    let mut shortened: String = text.chars().take(length - 3).collect();
+    shortened.push_str("...");
+    shortened
 }
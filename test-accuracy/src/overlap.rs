@@ -0,0 +1,247 @@
+use std::collections::HashSet;
+
+use anyhow::anyhow;
+use serde::Serialize;
+use tokio::fs;
+
+use crate::{truncate, OverlapContext};
+
+/// The size of the intersection of the two result sets divided by the
+/// size of their union.
+pub(crate) fn jaccard(a: &[String], b: &[String]) -> f64 {
+    let a: HashSet<&String> = a.iter().collect();
+    let b: HashSet<&String> = b.iter().collect();
+
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 1.0;
+    }
+
+    a.intersection(&b).count() as f64 / union as f64
+}
+
+/// The extrapolated rank-biased overlap of the two rankings.
+///
+/// Agreement at the top of the rankings is weighted more heavily than
+/// agreement further down, `persistence` controls how quickly the weight
+/// decays with a lower value being more top heavy.
+pub(crate) fn rank_biased_overlap(a: &[String], b: &[String], persistence: f64) -> f64 {
+    let depth = a.len().max(b.len());
+    if depth == 0 {
+        return 1.0;
+    }
+
+    let mut seen_a = HashSet::new();
+    let mut seen_b = HashSet::new();
+    let mut overlap = 0usize;
+    let mut weighted_sum = 0.0;
+
+    for d in 1..=depth {
+        let item_a = a.get(d - 1);
+        let item_b = b.get(d - 1);
+
+        if let (Some(x), Some(y)) = (item_a, item_b) {
+            if x == y {
+                overlap += 1;
+            } else {
+                overlap += seen_b.contains(x) as usize + seen_a.contains(y) as usize;
+            }
+        } else if let Some(x) = item_a {
+            overlap += seen_b.contains(x) as usize;
+        } else if let Some(y) = item_b {
+            overlap += seen_a.contains(y) as usize;
+        }
+
+        if let Some(x) = item_a {
+            seen_a.insert(x);
+        }
+        if let Some(y) = item_b {
+            seen_b.insert(y);
+        }
+
+        weighted_sum += (overlap as f64 / d as f64) * persistence.powi(d as i32);
+    }
+
+    let agreement = overlap as f64 / depth as f64;
+    agreement * persistence.powi(depth as i32)
+        + ((1.0 - persistence) / persistence) * weighted_sum
+}
+
+#[derive(Serialize)]
+struct PairScore {
+    a: String,
+    b: String,
+    jaccard: f64,
+    rbo: f64,
+}
+
+#[derive(Serialize)]
+struct QueryOverlap {
+    query: String,
+    results: Vec<Vec<String>>,
+    pairs: Vec<PairScore>,
+    mean_rbo: f64,
+}
+
+#[derive(Serialize)]
+struct Report {
+    k: usize,
+    persistence: f64,
+    targets: Vec<String>,
+    overall: Vec<PairScore>,
+    queries: Vec<QueryOverlap>,
+}
+
+pub(crate) async fn evaluate(ctx: OverlapContext, terms: Vec<String>) -> anyhow::Result<()> {
+    if ctx.targets.len() < 2 {
+        return Err(anyhow!("at least two targets are required to compare results"));
+    }
+
+    // The RBO is undefined at 0 and only the overlap at the full depth counts at 1.
+    if !(ctx.persistence > 0.0 && ctx.persistence < 1.0) {
+        return Err(anyhow!(
+            "the RBO persistence must be between 0 and 1 exclusive, got {}",
+            ctx.persistence
+        ));
+    }
+
+    if let Some(spec) = ctx.targets.iter().find(|spec| !spec.target.supports(ctx.kind)) {
+        return Err(anyhow!("{:?} does not support {:?} queries", spec.target, ctx.kind));
+    }
+
+    let labels: Vec<String> = ctx.targets.iter().map(|spec| spec.label()).collect();
+    let mut searchers = Vec::with_capacity(ctx.targets.len());
+    for spec in ctx.targets.iter() {
+        searchers.push(spec.searcher(&ctx.id_field).await?);
+    }
+
+    info!(
+        "comparing the top {} results of {} queries across {} targets",
+        ctx.k,
+        terms.len(),
+        searchers.len()
+    );
+
+    let mut queries = Vec::with_capacity(terms.len());
+    for query in terms {
+        let mut results = Vec::with_capacity(searchers.len());
+        for searcher in searchers.iter() {
+            results.push(searcher.search(&query, ctx.kind, ctx.k).await?);
+        }
+
+        let mut pairs = vec![];
+        for i in 0..results.len() {
+            for j in i + 1..results.len() {
+                pairs.push(PairScore {
+                    a: labels[i].clone(),
+                    b: labels[j].clone(),
+                    jaccard: jaccard(&results[i], &results[j]),
+                    rbo: rank_biased_overlap(&results[i], &results[j], ctx.persistence),
+                });
+            }
+        }
+
+        let mean_rbo = pairs.iter().map(|p| p.rbo).sum::<f64>() / pairs.len() as f64;
+        queries.push(QueryOverlap {
+            query,
+            results,
+            pairs,
+            mean_rbo,
+        });
+    }
+
+    let mut overall = vec![];
+    for i in 0..labels.len() {
+        for j in i + 1..labels.len() {
+            let scores: Vec<&PairScore> = queries
+                .iter()
+                .flat_map(|q| q.pairs.iter())
+                .filter(|p| p.a == labels[i] && p.b == labels[j])
+                .collect();
+
+            let count = scores.len().max(1) as f64;
+            overall.push(PairScore {
+                a: labels[i].clone(),
+                b: labels[j].clone(),
+                jaccard: scores.iter().map(|p| p.jaccard).sum::<f64>() / count,
+                rbo: scores.iter().map(|p| p.rbo).sum::<f64>() / count,
+            });
+        }
+    }
+
+    info!("Overall result overlap @ k={} (RBO p={}):", ctx.k, ctx.persistence);
+    info!("     {:<30} {:<30} {:>8} {:>8}", "Target", "Target", "Jaccard", "RBO");
+    for pair in overall.iter() {
+        info!(
+            "     {:<30} {:<30} {:>8.3} {:>8.3}",
+            truncate(&pair.a, 30),
+            truncate(&pair.b, 30),
+            pair.jaccard,
+            pair.rbo,
+        );
+    }
+
+    queries.sort_by(|a, b| a.mean_rbo.total_cmp(&b.mean_rbo));
+
+    info!("Most divergent queries:");
+    for query in queries.iter().take(ctx.divergent) {
+        info!("     {:?} (mean RBO {:.3})", query.query, query.mean_rbo);
+        for (label, results) in labels.iter().zip(query.results.iter()) {
+            info!("         {:<30} {:?}", truncate(label, 30), results);
+        }
+    }
+
+    if let Some(output) = ctx.output.as_ref() {
+        let report = Report {
+            k: ctx.k,
+            persistence: ctx.persistence,
+            targets: labels,
+            overall,
+            queries,
+        };
+
+        fs::write(output, serde_json::to_vec_pretty(&report)?).await?;
+        info!("Result has been saved to {}", output);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn identical_rankings() {
+        let a = ids(&["a", "b", "c", "d"]);
+
+        assert_eq!(jaccard(&a, &a), 1.0);
+        assert!((rank_biased_overlap(&a, &a, 0.9) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn disjoint_rankings() {
+        let a = ids(&["a", "b", "c"]);
+        let b = ids(&["x", "y", "z"]);
+
+        assert_eq!(jaccard(&a, &b), 0.0);
+        assert_eq!(rank_biased_overlap(&a, &b, 0.9), 0.0);
+    }
+
+    #[test]
+    fn top_heavy_agreement() {
+        let a = ids(&["a", "b", "c", "d"]);
+        let top_swapped = ids(&["b", "a", "c", "d"]);
+        let bottom_swapped = ids(&["a", "b", "d", "c"]);
+
+        assert_eq!(jaccard(&a, &top_swapped), jaccard(&a, &bottom_swapped));
+        assert!(
+            rank_biased_overlap(&a, &top_swapped, 0.9)
+                < rank_biased_overlap(&a, &bottom_swapped, 0.9)
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+
+use benchmark::typing_prefixes;
+use plotters::prelude::*;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use serde::Serialize;
+use tokio::fs;
+
+use crate::{truncate, Document, PrefixContext};
+
+#[derive(Serialize)]
+struct TitleResult {
+    id: String,
+    title: String,
+
+    /// The number of characters typed before the document appeared
+    /// in the top k results, `None` if it never appeared.
+    found_at: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct TargetReport {
+    target: String,
+    found_percent: f64,
+    mean_chars: f64,
+    median_chars: usize,
+    p90_chars: usize,
+
+    /// The mean percentage of the title typed before the document appeared.
+    mean_title_percent: f64,
+    titles: Vec<TitleResult>,
+}
+
+impl TargetReport {
+    fn new(target: String, titles: Vec<TitleResult>) -> Self {
+        let mut found: Vec<usize> = titles.iter().filter_map(|t| t.found_at).collect();
+        found.sort_unstable();
+
+        let percentile = |p: f64| {
+            if found.is_empty() {
+                0
+            } else {
+                found[((found.len() - 1) as f64 * p).round() as usize]
+            }
+        };
+
+        let mean = |values: &mut dyn Iterator<Item = f64>| {
+            let (total, count) = values.fold((0.0, 0usize), |(t, c), v| (t + v, c + 1));
+            if count == 0 {
+                0.0
+            } else {
+                total / count as f64
+            }
+        };
+
+        let mean_chars = mean(&mut found.iter().map(|v| *v as f64));
+        let mean_title_percent = mean(&mut titles.iter().filter_map(|t| {
+            t.found_at
+                .map(|at| at as f64 / t.title.chars().count() as f64 * 100.0)
+        }));
+
+        let found_percent = if titles.is_empty() {
+            0.0
+        } else {
+            found.len() as f64 / titles.len() as f64 * 100.0
+        };
+
+        Self {
+            target,
+            found_percent,
+            mean_chars,
+            median_chars: percentile(0.5),
+            p90_chars: percentile(0.9),
+            mean_title_percent,
+            titles,
+        }
+    }
+
+    /// The cumulative percentage of titles found at each prefix length.
+    fn cumulative(&self, max_length: usize) -> Vec<(usize, f64)> {
+        let mut counts: BTreeMap<usize, usize> = BTreeMap::new();
+        for at in self.titles.iter().filter_map(|t| t.found_at) {
+            *counts.entry(at).or_default() += 1;
+        }
+
+        let total = self.titles.len().max(1) as f64;
+        let mut running = 0;
+        (1..=max_length)
+            .map(|length| {
+                running += counts.get(&length).copied().unwrap_or(0);
+                (length, running as f64 / total * 100.0)
+            })
+            .collect()
+    }
+}
+
+pub(crate) async fn evaluate(ctx: PrefixContext, documents: Vec<Document>) -> anyhow::Result<()> {
+    let mut rng = StdRng::seed_from_u64(ctx.seed);
+    let documents: Vec<&Document> = documents.choose_multiple(&mut rng, ctx.samples).collect();
+
+    info!(
+        "typing out {} titles against {} targets @ k={}",
+        documents.len(),
+        ctx.targets.len(),
+        ctx.k
+    );
+
+    let mut reports = vec![];
+    for spec in ctx.targets.iter() {
+        let searcher = spec.searcher(&ctx.id_field).await?;
+
+        let mut titles = Vec::with_capacity(documents.len());
+        for doc in documents.iter() {
+            let mut found_at = None;
+            for (pos, query) in typing_prefixes(&doc.title).enumerate() {
+                let results = searcher.search(&query, ctx.kind, ctx.k).await?;
+                if results.contains(&doc.id) {
+                    found_at = Some(pos + 1);
+                    break;
+                }
+            }
+
+            titles.push(TitleResult {
+                id: doc.id.clone(),
+                title: doc.title.clone(),
+                found_at,
+            });
+        }
+
+        reports.push(TargetReport::new(spec.label(), titles));
+    }
+
+    info!("Prefix accuracy results (characters typed before the document is in the top {}):", ctx.k);
+    info!(
+        "     {:<30} {:>8} {:>8} {:>8} {:>8} {:>8}",
+        "Target", "Found", "Mean", "Median", "P90", "Title %"
+    );
+    for report in reports.iter() {
+        info!(
+            "     {:<30} {:>7.1}% {:>8.2} {:>8} {:>8} {:>7.1}%",
+            truncate(&report.target, 30),
+            report.found_percent,
+            report.mean_chars,
+            report.median_chars,
+            report.p90_chars,
+            report.mean_title_percent,
+        );
+    }
+
+    fs::create_dir_all(&ctx.output_dir).await?;
+
+    let max_length = documents
+        .iter()
+        .map(|doc| doc.title.chars().count())
+        .max()
+        .unwrap_or(1);
+    let chart = format!("{}/prefix-accuracy.png", ctx.output_dir);
+    draw_chart(&chart, &reports, max_length)?;
+    info!("Chart has been saved to {}", chart);
+
+    let output = format!("{}/prefix-accuracy.json", ctx.output_dir);
+    fs::write(&output, serde_json::to_vec_pretty(&reports)?).await?;
+    info!("Result has been saved to {}", output);
+
+    Ok(())
+}
+
+fn draw_chart(output: &str, reports: &[TargetReport], max_length: usize) -> anyhow::Result<()> {
+    let root = BitMapBackend::new(output, (1920, 1080)).into_drawing_area();
+
+    root.fill(&WHITE)?;
+
+    let mut chart = ChartBuilder::on(&root)
+        .x_label_area_size(75)
+        .y_label_area_size(75)
+        .margin(5)
+        .caption("Search As You Type Accuracy", ("sans-serif", 50.0))
+        .build_cartesian_2d(1usize..max_length.max(2), 0f64..100f64)?;
+
+    chart
+        .configure_mesh()
+        .bold_line_style(WHITE.mix(0.5))
+        .y_desc("Titles Found (%)")
+        .x_desc("Characters Typed")
+        .label_style(("sans-serif", 32))
+        .axis_desc_style(("sans-serif", 48))
+        .draw()?;
+
+    for (i, report) in reports.iter().enumerate() {
+        let colour = Palette99::pick(i).to_rgba();
+        chart
+            .draw_series(LineSeries::new(
+                report.cumulative(max_length),
+                colour.stroke_width(3),
+            ))?
+            .label(report.target.clone())
+            .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 40, y)], colour.stroke_width(3)));
+    }
+
+    chart
+        .configure_series_labels()
+        .label_font(("sans-serif", 32))
+        .background_style(WHITE.mix(0.8))
+        .border_style(BLACK)
+        .position(SeriesLabelPosition::LowerRight)
+        .draw()?;
+
+    // To avoid the IO failure being ignored silently, we manually call the present function
+    root.present()?;
+
+    Ok(())
+}
@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::targets::Searcher;
+use crate::{truncate, Context};
+
+/// The relevant documents of a judged query.
+///
+/// Either a list of ids which are all treated as a grade of `1` or
+/// a map of ids to their relevance grade.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Relevant {
+    Ids(Vec<String>),
+    Graded(HashMap<String, u32>),
+}
+
+#[derive(Debug, Deserialize)]
+struct RawJudgment {
+    query: String,
+    relevant: Relevant,
+}
+
+/// A query and the grades of its relevant documents.
+#[derive(Debug)]
+pub(crate) struct Judgment {
+    pub(crate) query: String,
+    pub(crate) grades: HashMap<String, u32>,
+}
+
+/// Parses a judgments file.
+///
+/// The file is a JSON list of `{"query": "...", "relevant": ...}` objects
+/// where `relevant` is either a list of document ids or a map of
+/// document ids to their relevance grade.
+pub(crate) fn parse_judgments(data: &str) -> anyhow::Result<Vec<Judgment>> {
+    let raw: Vec<RawJudgment> = serde_json::from_str(data)?;
+
+    let judgments = raw
+        .into_iter()
+        .map(|judgment| {
+            let grades = match judgment.relevant {
+                Relevant::Ids(ids) => ids.into_iter().map(|id| (id, 1)).collect(),
+                Relevant::Graded(grades) => grades,
+            };
+
+            Judgment {
+                query: judgment.query,
+                grades,
+            }
+        })
+        .collect();
+
+    Ok(judgments)
+}
+
+/// The relevance metrics of a single query at a cutoff of `k`.
+#[derive(Debug, Default, Clone, Serialize)]
+pub(crate) struct Metrics {
+    pub(crate) precision: f64,
+    pub(crate) recall: f64,
+    pub(crate) reciprocal_rank: f64,
+    pub(crate) ndcg: f64,
+}
+
+impl Metrics {
+    /// Calculates the metrics of the ranked results against the grades
+    /// considering only the top `k` results.
+    pub(crate) fn calculate(results: &[String], grades: &HashMap<String, u32>, k: usize) -> Self {
+        let top = &results[..results.len().min(k)];
+        let is_relevant = |id: &String| grades.get(id).copied().unwrap_or(0) > 0;
+
+        let relevant_total = grades.values().filter(|grade| **grade > 0).count();
+        let relevant_found = top.iter().filter(|id| is_relevant(id)).count();
+
+        let precision = if k == 0 {
+            0.0
+        } else {
+            relevant_found as f64 / k as f64
+        };
+
+        let recall = if relevant_total == 0 {
+            0.0
+        } else {
+            relevant_found as f64 / relevant_total as f64
+        };
+
+        let reciprocal_rank = top
+            .iter()
+            .position(is_relevant)
+            .map(|pos| 1.0 / (pos + 1) as f64)
+            .unwrap_or(0.0);
+
+        let dcg = discounted_gain(top.iter().map(|id| grades.get(id).copied().unwrap_or(0)));
+
+        let mut ideal: Vec<u32> = grades.values().copied().collect();
+        ideal.sort_unstable_by(|a, b| b.cmp(a));
+        let ideal_dcg = discounted_gain(ideal.into_iter().take(k));
+
+        let ndcg = if ideal_dcg == 0.0 { 0.0 } else { dcg / ideal_dcg };
+
+        Self {
+            precision,
+            recall,
+            reciprocal_rank,
+            ndcg,
+        }
+    }
+
+    /// The mean of each metric across all the given metrics.
+    pub(crate) fn mean<'a>(metrics: impl Iterator<Item = &'a Metrics>) -> Self {
+        let mut total = Self::default();
+        let mut count = 0usize;
+        for m in metrics {
+            total.precision += m.precision;
+            total.recall += m.recall;
+            total.reciprocal_rank += m.reciprocal_rank;
+            total.ndcg += m.ndcg;
+            count += 1;
+        }
+
+        if count == 0 {
+            return total;
+        }
+
+        let count = count as f64;
+        Self {
+            precision: total.precision / count,
+            recall: total.recall / count,
+            reciprocal_rank: total.reciprocal_rank / count,
+            ndcg: total.ndcg / count,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct QueryReport {
+    query: String,
+    results: Vec<String>,
+    metrics: Metrics,
+}
+
+#[derive(Serialize)]
+struct Report {
+    k: usize,
+    mean: Metrics,
+    queries: Vec<QueryReport>,
+}
+
+pub(crate) async fn evaluate(ctx: Context) -> anyhow::Result<()> {
+    let data = fs::read_to_string(&ctx.judgments).await?;
+    let judgments = parse_judgments(&data)?;
+    let searcher = Searcher::new(ctx.target, &ctx.address, &ctx.index, &ctx.id_field).await?;
+
+    info!(
+        "evaluating {} judged queries against {:?} @ k={}",
+        judgments.len(),
+        ctx.target,
+        ctx.k
+    );
+
+    let mut queries = Vec::with_capacity(judgments.len());
+    for judgment in judgments {
+        let results = searcher.search(&judgment.query, ctx.kind, ctx.k).await?;
+        let metrics = Metrics::calculate(&results, &judgment.grades, ctx.k);
+
+        queries.push(QueryReport {
+            query: judgment.query,
+            results,
+            metrics,
+        });
+    }
+
+    let mean = Metrics::mean(queries.iter().map(|q| &q.metrics));
+
+    info!("Per query results:");
+    info!(
+        "     {:<40} {:>8} {:>8} {:>8} {:>8}",
+        "Query",
+        format!("P@{}", ctx.k),
+        format!("R@{}", ctx.k),
+        "RR",
+        format!("nDCG@{}", ctx.k),
+    );
+    for query in queries.iter() {
+        info!(
+            "     {:<40} {:>8.3} {:>8.3} {:>8.3} {:>8.3}",
+            truncate(&query.query, 40),
+            query.metrics.precision,
+            query.metrics.recall,
+            query.metrics.reciprocal_rank,
+            query.metrics.ndcg,
+        );
+    }
+
+    info!("General accuracy results:");
+    info!("     Precision@{}: {:.3}", ctx.k, mean.precision);
+    info!("     Recall@{}: {:.3}", ctx.k, mean.recall);
+    info!("     MRR: {:.3}", mean.reciprocal_rank);
+    info!("     nDCG@{}: {:.3}", ctx.k, mean.ndcg);
+
+    if let Some(output) = ctx.output.as_ref() {
+        let report = Report {
+            k: ctx.k,
+            mean,
+            queries,
+        };
+
+        fs::write(output, serde_json::to_vec_pretty(&report)?).await?;
+        info!("Result has been saved to {}", output);
+    }
+
+    Ok(())
+}
+
+/// The discounted cumulative gain of the grades in ranked order.
+fn discounted_gain(grades: impl Iterator<Item = u32>) -> f64 {
+    grades
+        .enumerate()
+        .map(|(pos, grade)| (2f64.powi(grade as i32) - 1.0) / ((pos + 2) as f64).log2())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn perfect_ranking() {
+        let grades: HashMap<String, u32> = vec![("a".to_string(), 2), ("b".to_string(), 1)]
+            .into_iter()
+            .collect();
+
+        let metrics = Metrics::calculate(&ids(&["a", "b", "c"]), &grades, 2);
+        assert_eq!(metrics.precision, 1.0);
+        assert_eq!(metrics.recall, 1.0);
+        assert_eq!(metrics.reciprocal_rank, 1.0);
+        assert!((metrics.ndcg - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn partial_ranking() {
+        let grades: HashMap<String, u32> = vec![("a".to_string(), 1), ("b".to_string(), 1)]
+            .into_iter()
+            .collect();
+
+        let metrics = Metrics::calculate(&ids(&["x", "a", "y", "z"]), &grades, 4);
+        assert_eq!(metrics.precision, 0.25);
+        assert_eq!(metrics.recall, 0.5);
+        assert_eq!(metrics.reciprocal_rank, 0.5);
+
+        let expected = (1.0 / 3f64.log2()) / (1.0 + 1.0 / 3f64.log2());
+        assert!((metrics.ndcg - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn no_results() {
+        let grades: HashMap<String, u32> = vec![("a".to_string(), 1)].into_iter().collect();
+
+        let metrics = Metrics::calculate(&[], &grades, 10);
+        assert_eq!(metrics.precision, 0.0);
+        assert_eq!(metrics.recall, 0.0);
+        assert_eq!(metrics.reciprocal_rank, 0.0);
+        assert_eq!(metrics.ndcg, 0.0);
+    }
+
+    #[test]
+    fn judgments_formats() {
+        let judgments = parse_judgments(
+            r#"[
+                {"query": "cars", "relevant": ["1", "2"]},
+                {"query": "cars 2", "relevant": {"2": 3, "1": 1}}
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(judgments.len(), 2);
+        assert_eq!(judgments[0].grades.get("1"), Some(&1));
+        assert_eq!(judgments[1].grades.get("2"), Some(&3));
+    }
+}
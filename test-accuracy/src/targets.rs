@@ -0,0 +1,276 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use reqwest::header::HeaderValue;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::value_to_string;
+
+/// The search engines which can be evaluated.
+#[derive(Debug, Copy, Clone)]
+pub enum Target {
+    MeiliSearch,
+    Lnx,
+    TypeSense,
+}
+
+impl FromStr for Target {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "meilisearch" => Ok(Self::MeiliSearch),
+            "typesense" => Ok(Self::TypeSense),
+            "lnx" => Ok(Self::Lnx),
+            other => Err(format!(
+                "unknown target type got {:?}, expected one of 'meilisearch', 'typesense' or 'lnx'",
+                other,
+            )),
+        }
+    }
+}
+
+impl Target {
+    /// If the target can run the given kind of query.
+    pub(crate) fn supports(&self, kind: QueryKind) -> bool {
+        !matches!((self, kind), (Self::MeiliSearch, QueryKind::Normal))
+    }
+}
+
+/// A target, index and address to evaluate, written as
+/// `[<label>=]<target>:<index>@<address>`.
+///
+/// For example `lnx:movies@http://127.0.0.1:8000`, or
+/// `fast-fuzzy=lnx:movies_fast@http://127.0.0.1:8000` to tell apart indexes
+/// which only differ in their settings.
+#[derive(Debug, Clone)]
+pub struct TargetSpec {
+    pub target: Target,
+    pub index: String,
+    pub address: String,
+
+    /// The name shown in the reports instead of the target and index.
+    pub label: Option<String>,
+}
+
+impl TargetSpec {
+    /// The label used to identify the spec in reports.
+    pub(crate) fn label(&self) -> String {
+        match self.label.as_ref() {
+            Some(label) => label.clone(),
+            None => format!("{:?}:{}", self.target, self.index),
+        }
+    }
+
+    pub(crate) async fn searcher(&self, id_field: &str) -> anyhow::Result<Searcher> {
+        Searcher::new(self.target, &self.address, &self.index, id_field).await
+    }
+}
+
+impl FromStr for TargetSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || {
+            format!(
+                "invalid target spec {:?}, expected '[<label>=]<target>:<index>@<address>'",
+                s,
+            )
+        };
+
+        let (target, address) = s.split_once('@').ok_or_else(err)?;
+        let (label, target) = match target.split_once('=') {
+            Some(("", _)) => return Err(err()),
+            Some((label, target)) => (Some(label.to_string()), target),
+            None => (None, target),
+        };
+        let (target, index) = target.split_once(':').ok_or_else(err)?;
+
+        Ok(Self {
+            target: Target::from_str(target)?,
+            index: index.to_string(),
+            address: address.to_string(),
+            label,
+        })
+    }
+}
+
+/// The kind of query to send.
+///
+/// MeiliSearch is always typo tolerant so only supports fuzzy queries,
+/// TypeSense runs normal queries with typo tolerance disabled.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum QueryKind {
+    Fuzzy,
+    Normal,
+}
+
+impl QueryKind {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Self::Fuzzy => "fuzzy",
+            Self::Normal => "normal",
+        }
+    }
+}
+
+impl FromStr for QueryKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fuzzy" => Ok(Self::Fuzzy),
+            "normal" => Ok(Self::Normal),
+            other => Err(format!(
+                "unknown query kind got {:?}, expected either 'fuzzy' or 'normal'",
+                other,
+            )),
+        }
+    }
+}
+
+/// A client for a given target and index which returns the ranked
+/// document ids for a query.
+pub(crate) struct Searcher {
+    client: reqwest::Client,
+    target: Target,
+    address: String,
+    index: String,
+    id_field: String,
+
+    /// The fields TypeSense searches, this is empty for the other targets.
+    query_by: String,
+}
+
+#[derive(Serialize)]
+struct MeiliPayload<'a> {
+    q: &'a str,
+    limit: usize,
+}
+
+#[derive(Serialize)]
+struct TypeSensePayload<'a> {
+    q: &'a str,
+    query_by: &'a str,
+    per_page: usize,
+    num_typos: usize,
+}
+
+impl Searcher {
+    pub(crate) async fn new(
+        target: Target,
+        address: &str,
+        index: &str,
+        id_field: &str,
+    ) -> anyhow::Result<Self> {
+        let address = address.trim_end_matches('/').to_string();
+
+        // TypeSense searches the string fields of the collection in the same
+        // way as the benchmark.
+        let query_by = match target {
+            Target::TypeSense => benchmark::typesense_query_by(&address, index).await?,
+            _ => String::new(),
+        };
+
+        Ok(Self {
+            client: reqwest::Client::new(),
+            target,
+            address,
+            index: index.to_string(),
+            id_field: id_field.to_string(),
+            query_by,
+        })
+    }
+
+    /// Searches the target returning the ids of the top `limit` documents
+    /// in ranked order.
+    pub(crate) async fn search(
+        &self,
+        query: &str,
+        kind: QueryKind,
+        limit: usize,
+    ) -> anyhow::Result<Vec<String>> {
+        let request = match self.target {
+            Target::Lnx => {
+                let payload = serde_json::json!({
+                    "query": {
+                        kind.as_str(): { "ctx": query },
+                    },
+                    "limit": limit,
+                });
+
+                self.client
+                    .post(format!("{}/indexes/{}/search", self.address, self.index))
+                    .json(&payload)
+            },
+            Target::MeiliSearch => {
+                if kind == QueryKind::Normal {
+                    return Err(anyhow!("MeiliSearch does not support normal queries"));
+                }
+
+                self.client
+                    .post(format!("{}/indexes/{}/search", self.address, self.index))
+                    .json(&MeiliPayload { q: query, limit })
+            },
+            Target::TypeSense => {
+                let num_typos = if kind == QueryKind::Fuzzy { 2 } else { 0 };
+
+                self.client
+                    .get(format!(
+                        "{}/collections/{}/documents/search",
+                        self.address, self.index
+                    ))
+                    .header("X-TYPESENSE-API-KEY", HeaderValue::from_static("bench-key"))
+                    .query(&TypeSensePayload {
+                        q: query,
+                        query_by: &self.query_by,
+                        per_page: limit,
+                        num_typos,
+                    })
+            },
+        };
+
+        let r = request.send().await?;
+        let status = r.status();
+        if !status.is_success() {
+            return Err(anyhow!(
+                "got unexpected response code {} data: {}",
+                status,
+                r.text().await?
+            ));
+        }
+
+        let data: Value = r.json().await?;
+        self.extract_ids(&data)
+    }
+
+    fn extract_ids(&self, data: &Value) -> anyhow::Result<Vec<String>> {
+        let (hits, doc_key) = match self.target {
+            Target::Lnx => (data.pointer("/data/hits"), Some("doc")),
+            Target::MeiliSearch => (data.get("hits"), None),
+            Target::TypeSense => (data.get("hits"), Some("document")),
+        };
+
+        let hits = hits
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| anyhow!("response did not contain a list of hits: {}", data))?;
+
+        let mut ids = Vec::with_capacity(hits.len());
+        for hit in hits {
+            let doc = match doc_key {
+                Some(key) => hit.get(key).unwrap_or(&Value::Null),
+                None => hit,
+            };
+
+            let id = doc
+                .get(&self.id_field)
+                .and_then(value_to_string)
+                .ok_or_else(|| anyhow!("hit is missing the {:?} field: {}", self.id_field, hit))?;
+
+            ids.push(id);
+        }
+
+        Ok(ids)
+    }
+}
@@ -0,0 +1,277 @@
+use std::collections::BTreeMap;
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+use tokio::fs;
+
+use crate::targets::{QueryKind, Searcher};
+use crate::{Document, TypoContext};
+
+/// The edits applied to a title to produce a misspelling.
+#[derive(Debug, Copy, Clone)]
+enum Edit {
+    /// Swaps the character with the next one, e.g. `dragon` -> `dargon`.
+    Swap,
+
+    /// Drops the character, e.g. `dragon` -> `dragn`.
+    Drop,
+
+    /// Doubles the character, e.g. `dragon` -> `dragonn`.
+    Double,
+}
+
+const EDITS: [Edit; 3] = [Edit::Swap, Edit::Drop, Edit::Double];
+
+/// Misspells the text with the given number of edits.
+///
+/// Each edit is applied to a different letter and edits are never
+/// adjacent to one another so they cannot cancel each other out,
+/// `None` is returned if the text doesn't have enough letters.
+pub(crate) fn misspell(text: &str, distance: usize, rng: &mut impl Rng) -> Option<String> {
+    let mut chars: Vec<char> = text.chars().collect();
+    let mut candidates: Vec<usize> = chars
+        .iter()
+        .enumerate()
+        .filter(|(pos, c)| {
+            // Swaps need a following letter which is different to this one.
+            c.is_alphabetic() && chars.get(pos + 1).map(|n| n != *c).unwrap_or(true)
+        })
+        .map(|(pos, _)| pos)
+        .collect();
+
+    let mut positions: Vec<usize> = vec![];
+    candidates.shuffle(rng);
+    for pos in candidates {
+        if positions.len() == distance {
+            break;
+        }
+
+        if positions.iter().all(|p| (*p as isize - pos as isize).abs() > 2) {
+            positions.push(pos);
+        }
+    }
+
+    if positions.len() < distance {
+        return None;
+    }
+
+    // Apply the edits from the end so earlier positions stay valid.
+    positions.sort_unstable_by(|a, b| b.cmp(a));
+    for pos in positions {
+        let can_swap = chars
+            .get(pos + 1)
+            .map(|c| c.is_alphabetic() && *c != chars[pos])
+            .unwrap_or(false);
+
+        let edit = loop {
+            let edit = *EDITS.choose(rng).unwrap();
+            if can_swap || !matches!(edit, Edit::Swap) {
+                break edit;
+            }
+        };
+
+        match edit {
+            Edit::Swap => chars.swap(pos, pos + 1),
+            Edit::Drop => {
+                chars.remove(pos);
+            },
+            Edit::Double => chars.insert(pos, chars[pos]),
+        }
+    }
+
+    Some(chars.into_iter().collect())
+}
+
+#[derive(Serialize)]
+struct Miss {
+    id: String,
+    title: String,
+    query: String,
+    distance: usize,
+    kind: &'static str,
+    results: Vec<String>,
+}
+
+#[derive(Default, Serialize)]
+struct HitRate {
+    hits: usize,
+    total: usize,
+}
+
+impl HitRate {
+    fn percent(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / self.total as f64 * 100.0
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Report {
+    k: usize,
+    hit_rates: BTreeMap<String, BTreeMap<usize, HitRate>>,
+    misses: Vec<Miss>,
+}
+
+pub(crate) async fn evaluate(ctx: TypoContext, documents: Vec<Document>) -> anyhow::Result<()> {
+    let searcher = Searcher::new(ctx.target, &ctx.address, &ctx.index, &ctx.id_field).await?;
+    let mut rng = StdRng::seed_from_u64(ctx.seed);
+
+    let kinds: Vec<QueryKind> = ctx
+        .kinds
+        .iter()
+        .copied()
+        .filter(|kind| {
+            let supported = ctx.target.supports(*kind);
+            if !supported {
+                warn!("{:?} does not support {:?} queries, skipping", ctx.target, kind);
+            }
+            supported
+        })
+        .collect();
+
+    let documents: Vec<&Document> = documents.choose_multiple(&mut rng, ctx.samples).collect();
+
+    info!(
+        "checking {} titles at edit distances 0 to {} against {:?} @ k={}",
+        documents.len(),
+        ctx.max_distance,
+        ctx.target,
+        ctx.k
+    );
+
+    let mut hit_rates: BTreeMap<String, BTreeMap<usize, HitRate>> = BTreeMap::new();
+    let mut misses = vec![];
+    for doc in documents {
+        for distance in 0..=ctx.max_distance {
+            let query = match misspell(&doc.title, distance, &mut rng) {
+                Some(query) => query,
+                None => continue,
+            };
+
+            for kind in kinds.iter() {
+                let results = searcher.search(&query, *kind, ctx.k).await?;
+                let hit = results.contains(&doc.id);
+
+                let rate = hit_rates
+                    .entry(kind.as_str().to_string())
+                    .or_default()
+                    .entry(distance)
+                    .or_default();
+
+                rate.total += 1;
+                if hit {
+                    rate.hits += 1;
+                } else {
+                    misses.push(Miss {
+                        id: doc.id.clone(),
+                        title: doc.title.clone(),
+                        query: query.clone(),
+                        distance,
+                        kind: kind.as_str(),
+                        results,
+                    });
+                }
+            }
+        }
+    }
+
+    info!("Typo tolerance results (source document in top {}):", ctx.k);
+    info!(
+        "     {:<10} {}",
+        "Distance",
+        kinds
+            .iter()
+            .map(|kind| format!("{:>10}", kind.as_str()))
+            .collect::<String>()
+    );
+    for distance in 0..=ctx.max_distance {
+        let row: String = kinds
+            .iter()
+            .map(|kind| {
+                let rate = hit_rates
+                    .get(kind.as_str())
+                    .and_then(|rates| rates.get(&distance));
+
+                match rate {
+                    Some(rate) => format!("{:>9.1}%", rate.percent()),
+                    None => format!("{:>10}", "-"),
+                }
+            })
+            .collect();
+
+        info!("     {:<10} {}", distance, row);
+    }
+    info!("     Total misses: {}", misses.len());
+
+    if let Some(output) = ctx.output.as_ref() {
+        let report = Report {
+            k: ctx.k,
+            hit_rates,
+            misses,
+        };
+
+        fs::write(output, serde_json::to_vec_pretty(&report)?).await?;
+        info!("Result has been saved to {}", output);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn distance(a: &str, b: &str) -> usize {
+        // Optimal string alignment distance, a swap counts as one edit.
+        let a: Vec<char> = a.chars().collect();
+        let b: Vec<char> = b.chars().collect();
+        let mut d = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+        for (i, row) in d.iter_mut().enumerate() {
+            row[0] = i;
+        }
+        for (j, cell) in d[0].iter_mut().enumerate() {
+            *cell = j;
+        }
+
+        for i in 1..=a.len() {
+            for j in 1..=b.len() {
+                let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+                d[i][j] = (d[i - 1][j] + 1)
+                    .min(d[i][j - 1] + 1)
+                    .min(d[i - 1][j - 1] + cost);
+
+                if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                    d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+                }
+            }
+        }
+
+        d[a.len()][b.len()]
+    }
+
+    #[test]
+    fn misspellings_have_the_requested_distance() {
+        let mut rng = StdRng::seed_from_u64(1234);
+        let titles = ["how to train your dragon", "the truman show", "breaking bad"];
+
+        for title in titles.iter() {
+            for edits in 0..=3 {
+                for _ in 0..50 {
+                    let typo = misspell(title, edits, &mut rng).unwrap();
+                    assert_eq!(distance(title, &typo), edits, "{:?} -> {:?}", title, typo);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn short_text_is_skipped() {
+        let mut rng = StdRng::seed_from_u64(1234);
+        assert!(misspell("up", 3, &mut rng).is_none());
+    }
+}